@@ -0,0 +1,238 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt,
+};
+
+use cubing::alg::{Alg, Move};
+use itertools::Itertools;
+use serde::{Serialize, Serializer, ser::SerializeStruct};
+
+use crate::{Grip, MoveSeq, Puzzle, Region};
+
+/// Result of analyzing an algorithm on a puzzle.
+#[derive(Debug, Default, Clone)]
+pub struct Analysis {
+    /// Setup moves, which are applied before [`Analysis::moves`] so that
+    /// regions are described relative to the solved state.
+    pub setup: MoveSeq,
+    /// Moves of the algorithm, with grouping, commutators, and conjugates
+    /// expanded.
+    pub moves: MoveSeq,
+    /// Regions of the puzzle that are affected differently by the algorithm.
+    pub regions: BTreeSet<Region>,
+    /// Grips referenced by any region.
+    pub grips: BTreeSet<Grip>,
+    /// Regions grouped by net move sequence of [`Analysis::moves`] and then by
+    /// move mask, which contains one boolean per move in [`Analysis::setup`]
+    /// and then in [`Analysis::moves`] indicating whether that move affects
+    /// the region.
+    pub results: BTreeMap<MoveSeq, BTreeMap<Vec<bool>, Vec<Region>>>,
+}
+
+impl Analysis {
+    /// Returns the setup moves followed by the moves of the algorithm, which
+    /// correspond to the entries of a move mask.
+    pub fn all_moves(&self) -> impl Iterator<Item = &Move> {
+        self.setup.iter().chain(self.moves.iter())
+    }
+}
+
+impl fmt::Display for Analysis {
+    /// Formats the analysis as plain text, with the same sections as the GUI.
+    /// Moves that do not affect a region are shown in parentheses, and setup
+    /// moves are separated from the algorithm by `|`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if !self.setup.is_empty() {
+            writeln!(f, "Setup: {}", self.setup)?;
+        }
+        writeln!(f, "Moves: {}", self.moves)?;
+
+        writeln!(f, "Grips ({}):", self.grips.len())?;
+        for g in &self.grips {
+            writeln!(f, "    {g}")?;
+        }
+
+        writeln!(f, "Regions ({}):", self.regions.len())?;
+        for r in &self.regions {
+            writeln!(f, "    {r}")?;
+        }
+
+        writeln!(f, "Results ({}):", self.results.len())?;
+        for (move_seq, regions_by_move_seq) in &self.results {
+            if move_seq.is_empty() {
+                writeln!(f, "    Net move sequence: (empty)")?;
+            } else {
+                writeln!(f, "    Net move sequence: {move_seq}")?;
+            }
+            for (move_mask, regions) in regions_by_move_seq {
+                let mut masked_moves = self
+                    .all_moves()
+                    .zip(move_mask)
+                    .map(|(m, &include)| match include {
+                        true => m.to_string(),
+                        false => format!("({m})"),
+                    })
+                    .collect_vec();
+                if !self.setup.is_empty() {
+                    masked_moves.insert(self.setup.len(), "|".to_string());
+                }
+                let masked_moves = masked_moves.join(" ");
+                writeln!(f, "        {masked_moves}")?;
+                for r in regions {
+                    writeln!(f, "            {r}")?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Version of the JSON format produced by serializing an [`Analysis`]. This is
+/// incremented whenever the format changes incompatibly.
+pub const JSON_FORMAT_VERSION: u32 = 2;
+
+impl Serialize for Analysis {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        #[derive(Serialize)]
+        struct NetMoves<'a> {
+            net_moves: &'a MoveSeq,
+            masks: Vec<Mask<'a>>,
+        }
+        #[derive(Serialize)]
+        struct Mask<'a> {
+            move_mask: &'a [bool],
+            regions: &'a [Region],
+        }
+
+        let results = self
+            .results
+            .iter()
+            .map(|(net_moves, regions_by_mask)| NetMoves {
+                net_moves,
+                masks: regions_by_mask
+                    .iter()
+                    .map(|(move_mask, regions)| Mask { move_mask, regions })
+                    .collect(),
+            })
+            .collect_vec();
+
+        let mut s = serializer.serialize_struct("Analysis", 6)?;
+        s.serialize_field("version", &JSON_FORMAT_VERSION)?;
+        s.serialize_field("setup", &self.setup)?;
+        s.serialize_field("moves", &self.moves)?;
+        s.serialize_field("grips", &self.grips)?;
+        s.serialize_field("regions", &self.regions)?;
+        s.serialize_field("results", &results)?;
+        s.end()
+    }
+}
+
+/// Analyzes `alg` on `puzzle`.
+///
+/// Returns an error if the relations rewrite a grip forever.
+pub fn analyze(alg: &Alg, puzzle: &Puzzle) -> Result<Analysis, String> {
+    analyze_with_setup(&Alg::default(), alg, puzzle)
+}
+
+/// Analyzes `alg` on `puzzle` after the moves of `setup`.
+///
+/// Returns an error if the relations rewrite a grip forever.
+pub fn analyze_with_setup(setup: &Alg, alg: &Alg, puzzle: &Puzzle) -> Result<Analysis, String> {
+    let setup_moves = MoveSeq::from_alg_in_order(setup, puzzle);
+    let moves = MoveSeq::from_alg_in_order(alg, puzzle);
+
+    // The moves are inverted one by one so that the setup and the algorithm
+    // do not cancel, because the regions must be split by every move of both
+    let mut regions = BTreeSet::from_iter([Region::default()]);
+    for m in setup_moves.iter().chain(moves.iter()).rev() {
+        let mut new_regions = BTreeSet::new();
+        for r in regions {
+            let (not_affected, affected) = r.do_move(m.invert(), puzzle)?;
+            new_regions
+                .extend(itertools::chain(not_affected, affected).filter(|r| puzzle.is_possible(r)));
+        }
+        regions = new_regions;
+    }
+
+    let grips = regions
+        .iter()
+        .flat_map(|r| itertools::chain(&r.include, &r.exclude))
+        .cloned()
+        .collect();
+
+    let mut results = BTreeMap::<MoveSeq, BTreeMap<Vec<bool>, Vec<Region>>>::new();
+    for region in &regions {
+        let mut move_seq = MoveSeq::new();
+        let mut move_mask = vec![];
+        let mut r = region.clone();
+        for (i, m) in setup_moves.iter().chain(moves.iter()).enumerate() {
+            // `r` is already split by every move, so it is either affected or
+            // not affected as a whole.
+            let (not_affected, mut affected) = r.do_move(m.clone(), puzzle)?;
+            move_mask.push(!affected.is_empty());
+            if !affected.is_empty() && i >= setup_moves.len() {
+                move_seq.push_back(m.clone(), puzzle);
+            }
+            r = affected.pop().or(not_affected).unwrap();
+        }
+        results
+            .entry(move_seq)
+            .or_default()
+            .entry(move_mask)
+            .or_default()
+            .push(region.clone());
+    }
+
+    Ok(Analysis {
+        setup: setup_moves,
+        moves,
+        regions,
+        grips,
+        results,
+    })
+}
+
+/// Regions of the puzzle partway through an algorithm, found by
+/// [`step_through`].
+#[derive(Debug, Default, Clone)]
+pub struct Step {
+    /// Regions that the moves so far affect differently, with their grips
+    /// transformed by those moves.
+    pub regions: BTreeSet<Region>,
+    /// Regions that the next move splits into several regions.
+    pub split_by_next_move: BTreeSet<Region>,
+}
+
+/// Steps through the setup and moves of an analysis, splitting the regions by
+/// each move in turn. Returns the step before each move followed by the step
+/// after the last move.
+///
+/// Returns an error if the relations rewrite a grip forever.
+pub fn step_through(analysis: &Analysis, puzzle: &Puzzle) -> Result<Vec<Step>, String> {
+    let mut ret = vec![];
+    let mut regions = BTreeSet::from_iter([Region::default()]);
+    for m in analysis.all_moves() {
+        let mut new_regions = BTreeSet::new();
+        let mut split_by_next_move = BTreeSet::new();
+        for r in &regions {
+            let (not_affected, affected) = r.clone().do_move(m.clone(), puzzle)?;
+            let parts = itertools::chain(not_affected, affected)
+                .filter(|r| puzzle.is_possible(r))
+                .collect_vec();
+            if parts.len() > 1 {
+                split_by_next_move.insert(r.clone());
+            }
+            new_regions.extend(parts);
+        }
+        ret.push(Step {
+            regions: std::mem::replace(&mut regions, new_regions),
+            split_by_next_move,
+        });
+    }
+    ret.push(Step {
+        regions,
+        ..Default::default()
+    });
+    Ok(ret)
+}
@@ -0,0 +1,247 @@
+use std::{
+    io::{BufRead, Read},
+    path::Path,
+    process::ExitCode,
+};
+
+use grippy::{
+    DEFAULT_RELATIONS, Preset, Puzzle, Session, analyze_with_setup, check_consistency, complete,
+    critical_pairs, derive_relations, load_kpuzzle, parse_alg, verify,
+};
+
+const USAGE: &str = "\
+Usage: grippy-cli [OPTIONS] [ALG]...
+
+Analyzes each ALG and prints its grips, regions, and results. If no ALG is
+given, algorithms are read from stdin, one per line.
+
+Options:
+    -r, --relations <FILE>  Read relations from FILE ('-' for stdin)
+    -p, --preset <NAME>     Use the relations of a built-in puzzle preset
+    -s, --session <FILE>    Use the relations of a session saved by the GUI, and
+                            analyze its algorithm if no ALG is given
+    -l, --permalink <LINK>  Use the relations and algorithm of a permalink
+                            copied from the GUI, like a session, or of an
+                            alg.cubing.net or twizzle URL
+        --setup <ALG>       Apply ALG before each algorithm, so that regions are
+                            relative to the solved state (defaults to the setup
+                            of the session or permalink)
+    -k, --kpuzzle <KPUZZLE> Derive relations from a KPuzzle, either a built-in
+                            one (3x3x3 or 2x2x2) or a JSON definition file
+    -v, --verify <KPUZZLE>  Check each analysis by simulating the algorithm on
+                            every piece of a KPuzzle
+    -c, --complete          Add relations until no two relations rewrite a grip
+                            differently before analyzing
+        --check-confluence  Print the grips that two relations rewrite
+                            differently instead of analyzing algorithms
+    -j, --json              Print one JSON object per algorithm instead of text
+    -h, --help              Print this help
+";
+
+fn main() -> ExitCode {
+    match run() {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::FAILURE,
+        Err(e) => {
+            eprintln!("error: {e}");
+            eprintln!();
+            eprint!("{USAGE}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// Returns whether every algorithm was analyzed successfully.
+fn run() -> Result<bool, String> {
+    let mut relations_path = None;
+    let mut session_path = None;
+    let mut session = None;
+    let mut preset = None;
+    let mut setup_str = None;
+    let mut kpuzzle = None;
+    let mut verify_kpuzzle = None;
+    let mut complete_relations = false;
+    let mut check_confluence = false;
+    let mut json = false;
+    let mut algs = vec![];
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-h" | "--help" => {
+                print!("{USAGE}");
+                return Ok(true);
+            }
+            "-r" | "--relations" => {
+                relations_path = Some(args.next().ok_or("missing relations file")?);
+            }
+            "-s" | "--session" => {
+                let path = args.next().ok_or("missing session file")?;
+                session = Some(Session::load(Path::new(&path))?);
+                session_path = Some(path);
+            }
+            "-l" | "--permalink" => {
+                session = Some(Session::from_permalink(
+                    &args.next().ok_or("missing permalink")?,
+                )?);
+                session_path = None;
+            }
+            "-p" | "--preset" => {
+                preset = Some(
+                    args.next()
+                        .ok_or("missing preset name")?
+                        .parse::<Preset>()?,
+                );
+            }
+            "--setup" => setup_str = Some(args.next().ok_or("missing setup")?),
+            "-k" | "--kpuzzle" => {
+                kpuzzle = Some(load_kpuzzle(&args.next().ok_or("missing KPuzzle")?)?);
+            }
+            "-v" | "--verify" => {
+                verify_kpuzzle = Some(load_kpuzzle(&args.next().ok_or("missing KPuzzle")?)?);
+            }
+            "-c" | "--complete" => complete_relations = true,
+            "--check-confluence" => check_confluence = true,
+            "-j" | "--json" => json = true,
+            _ => algs.push(arg),
+        }
+    }
+
+    if session.is_some() && relations_path.is_some() {
+        return Err("cannot use both relations and a session or permalink".to_string());
+    }
+    if let Some(session) = &session
+        && algs.is_empty()
+    {
+        algs.push(session.alg.clone());
+    }
+
+    let relations_str = match (relations_path.as_deref(), &session) {
+        (None, Some(session)) => session.relations.clone(),
+        (None, None) if preset.is_some() || kpuzzle.is_some() => String::new(),
+        (None, None) => DEFAULT_RELATIONS.to_string(),
+        (Some("-"), _) => {
+            let mut s = String::new();
+            std::io::stdin()
+                .read_to_string(&mut s)
+                .map_err(|e| format!("error reading relations from stdin: {e}"))?;
+            s
+        }
+        (Some(path), _) => std::fs::read_to_string(path)
+            .map_err(|e| format!("error reading relations from {path:?}: {e}"))?,
+    };
+    let relations_str = match preset {
+        Some(preset) => format!("{}\n{relations_str}", preset.relations()),
+        None => relations_str,
+    };
+    let relations_str = match &kpuzzle {
+        Some(kpuzzle) => format!("{}\n{relations_str}", derive_relations(kpuzzle)?),
+        None => relations_str,
+    };
+    // Included files are relative to the relations or session file
+    let puzzle = match relations_path.as_deref().or(session_path.as_deref()) {
+        None | Some("-") => Puzzle::from_str_in_dir(&relations_str, Path::new(".")),
+        Some(path) => Puzzle::from_str_in_file(&relations_str, Path::new(path)),
+    };
+    let mut puzzle = match puzzle {
+        Ok(puzzle) => puzzle,
+        Err(errors) => {
+            for e in errors {
+                eprintln!("error: {e}");
+            }
+            return Ok(false);
+        }
+    };
+    for issue in check_consistency(&puzzle)? {
+        eprintln!("warning: {issue}");
+    }
+
+    if check_confluence {
+        let pairs = critical_pairs(&puzzle)?;
+        for pair in &pairs {
+            println!("{pair}");
+        }
+        println!("{} critical pairs rewrite differently", pairs.len());
+        return Ok(pairs.is_empty());
+    }
+    if complete_relations {
+        for r in complete(&mut puzzle)? {
+            eprintln!("added relation: {r}");
+        }
+    }
+
+    if algs.is_empty() {
+        if relations_path.as_deref() == Some("-") {
+            return Err("cannot read both relations and algorithms from stdin".to_string());
+        }
+        for line in std::io::stdin().lock().lines() {
+            let line = line.map_err(|e| format!("error reading algorithms from stdin: {e}"))?;
+            if !line.trim().is_empty() {
+                algs.push(line);
+            }
+        }
+    }
+
+    let setup_str = setup_str
+        .or_else(|| session.as_ref().map(|s| s.setup.clone()))
+        .unwrap_or_default();
+    let setup = parse_alg(&setup_str).map_err(|e| {
+        format!(
+            "invalid setup {setup_str:?}: column {}: {}",
+            e.columns.start, e.message,
+        )
+    })?;
+
+    let mut all_ok = true;
+    for (i, alg_str) in algs.iter().enumerate() {
+        if i > 0 && !json {
+            println!();
+        }
+        let alg = match parse_alg(alg_str) {
+            Ok(alg) => alg,
+            Err(e) => {
+                eprintln!(
+                    "error: invalid algorithm {alg_str:?}: column {}: {}",
+                    e.columns.start, e.message,
+                );
+                all_ok = false;
+                continue;
+            }
+        };
+        let analysis = match analyze_with_setup(&setup, &alg, &puzzle) {
+            Ok(analysis) => analysis,
+            Err(e) => {
+                eprintln!("error: cannot analyze {alg_str:?}: {e}");
+                all_ok = false;
+                continue;
+            }
+        };
+        if json {
+            println!(
+                "{}",
+                serde_json::to_string(&analysis).map_err(|e| e.to_string())?
+            );
+        } else {
+            println!("Algorithm: {}", alg_str.trim());
+            print!("{analysis}");
+        }
+        if let Some(kpuzzle) = &verify_kpuzzle {
+            let verification = match verify(&analysis, &puzzle, kpuzzle) {
+                Ok(verification) => verification,
+                Err(e) => {
+                    eprintln!("error: cannot verify {alg_str:?}: {e}");
+                    all_ok = false;
+                    continue;
+                }
+            };
+            // Keep stdout valid JSON lines
+            if json {
+                eprint!("{verification}");
+            } else {
+                print!("{verification}");
+            }
+            all_ok &= verification.is_ok();
+        }
+    }
+    Ok(all_ok)
+}
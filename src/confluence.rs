@@ -0,0 +1,133 @@
+use std::{collections::BTreeMap, fmt};
+
+use itertools::Itertools;
+
+use crate::{Grip, MoveSeq, Puzzle, Relation};
+
+/// Maximum number of relations added by [`complete`].
+const MAX_COMPLETION_RELATIONS: usize = 100;
+
+/// Grip that two relations rewrite differently, with the results of applying
+/// each relation and then all relations until none match.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CriticalPair {
+    pub grip: Grip,
+    pub rewrites: [(Relation, Grip); 2],
+}
+impl fmt::Display for CriticalPair {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let [(r1, g1), (r2, g2)] = &self.rewrites;
+        write!(
+            f,
+            "{} rewrites to {g1} by `{r1}` but to {g2} by `{r2}`",
+            self.grip,
+        )
+    }
+}
+
+/// Returns the critical pairs of the relations of a puzzle that do not
+/// rewrite to the same grip, which means that the result of rewriting a grip
+/// depends on the order of the relations.
+///
+/// Relations overlap when they replace the same grip and the transform of one
+/// starts with the transform of the other, so that both apply to the grip with
+/// the longer transform.
+pub fn critical_pairs(puzzle: &Puzzle) -> Result<Vec<CriticalPair>, String> {
+    let relations_by_grip = puzzle
+        .relations
+        .iter()
+        .into_group_map_by(|r| &r.grip_to_replace.grip_name)
+        .into_iter()
+        .collect::<BTreeMap<_, _>>();
+
+    let mut ret = vec![];
+    for relations in relations_by_grip.values() {
+        for (&r1, &r2) in relations.iter().tuple_combinations() {
+            let (r1, r2) = match (
+                starts_with(&r2.grip_to_replace, &r1.grip_to_replace),
+                starts_with(&r1.grip_to_replace, &r2.grip_to_replace),
+            ) {
+                (true, _) => (r1, r2),
+                (false, true) => (r2, r1),
+                (false, false) => continue,
+            };
+            let grip = &r2.grip_to_replace;
+            let rewrite = |r: &Relation| -> Result<Grip, String> {
+                r.apply(grip, puzzle)
+                    .expect("relation matches")
+                    .normalize(puzzle)
+            };
+            let (g1, g2) = (rewrite(r1)?, rewrite(r2)?);
+            if g1 != g2 {
+                ret.push(CriticalPair {
+                    grip: grip.clone(),
+                    rewrites: [(r1.clone(), g1), (r2.clone(), g2)],
+                });
+            }
+        }
+    }
+    Ok(ret)
+}
+
+/// Adds relations to a puzzle until it has no critical pairs that rewrite to
+/// different grips, using Knuth-Bendix completion. Returns the added relations.
+///
+/// Each critical pair is resolved by a relation that rewrites the result with
+/// the longer transform to the other. Completion fails if the relations
+/// rewrite a grip forever or if it does not finish after adding
+/// [`MAX_COMPLETION_RELATIONS`] relations.
+pub fn complete(puzzle: &mut Puzzle) -> Result<Vec<Relation>, String> {
+    let mut added = vec![];
+    while let Some(pair) = critical_pairs(puzzle)?.into_iter().next() {
+        if added.len() >= MAX_COMPLETION_RELATIONS {
+            return Err(format!(
+                "completion did not finish after adding {MAX_COMPLETION_RELATIONS} relations",
+            ));
+        }
+        let [(_, g1), (_, g2)] = pair.rewrites;
+        let (grip_to_replace, new_grip) = if (g1.transform.len(), &g1) > (g2.transform.len(), &g2) {
+            (g1, g2)
+        } else {
+            (g2, g1)
+        };
+        let relation = Relation {
+            new_grip,
+            grip_to_replace,
+            line: None,
+        };
+        puzzle.relations.push(relation.clone());
+        added.push(relation);
+    }
+    Ok(added)
+}
+
+/// Returns whether `grip` has the same name as `prefix` and its transform
+/// starts with the transform of `prefix`.
+fn starts_with(grip: &Grip, prefix: &Grip) -> bool {
+    let mut transform: MoveSeq = grip.transform.clone();
+    grip.grip_name == prefix.grip_name && transform.pop_front_if_matches(&prefix.transform)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn relations_without_overlap_are_confluent() {
+        let puzzle: Puzzle = "U = F * R\nR = U * F".parse().unwrap();
+        assert!(critical_pairs(&puzzle).unwrap().is_empty());
+    }
+
+    #[test]
+    fn complete_resolves_critical_pair() {
+        let mut puzzle: Puzzle = "A = U * R\nB = U * R U".parse().unwrap();
+        let pairs = critical_pairs(&puzzle).unwrap();
+        assert_eq!(pairs.len(), 1);
+        assert_eq!(pairs[0].grip.to_string(), "U × (R U)");
+
+        let added = complete(&mut puzzle).unwrap();
+        let added = added.iter().map(|r| r.to_string()).collect_vec();
+        assert_eq!(added, ["B = A * U"]);
+        assert!(critical_pairs(&puzzle).unwrap().is_empty());
+    }
+}
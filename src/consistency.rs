@@ -0,0 +1,171 @@
+use std::fmt;
+
+use crate::{Puzzle, Relation};
+
+/// Kind of problem with a relation found by [`check_consistency`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum IssueKind {
+    /// The relation rewrites a grip to a different grip than another relation.
+    Contradictory,
+    /// The relation duplicates another relation or follows from the others.
+    Redundant,
+    /// The relation never applies.
+    Unreachable,
+}
+impl fmt::Display for IssueKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Contradictory => "contradictory",
+            Self::Redundant => "redundant",
+            Self::Unreachable => "unreachable",
+        })
+    }
+}
+
+/// Problem with a relation.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Issue {
+    pub kind: IssueKind,
+    pub relation: Relation,
+    pub message: String,
+}
+impl fmt::Display for Issue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Self {
+            kind,
+            relation,
+            message,
+        } = self;
+        if let Some(line) = relation.line {
+            write!(f, "line {line}: ")?;
+        }
+        write!(f, "{kind} relation `{relation}`: {message}")
+    }
+}
+
+/// Checks the relations of a puzzle for contradictory, redundant, and
+/// unreachable relations. Each relation line is reported at most once per
+/// kind of issue, even though it is parsed into a relation in each
+/// direction.
+///
+/// Returns an error if the relations rewrite a grip forever.
+pub fn check_consistency(puzzle: &Puzzle) -> Result<Vec<Issue>, String> {
+    let mut ret: Vec<Issue> = vec![];
+    let mut report = |kind, relation: &Relation, message| {
+        let is_reported = ret.iter().any(|i| {
+            i.kind == kind && i.relation.line.is_some() && i.relation.line == relation.line
+        });
+        if !is_reported {
+            ret.push(Issue {
+                kind,
+                relation: relation.clone(),
+                message,
+            });
+        }
+    };
+    let describe = |r: &Relation| match r.line {
+        Some(line) => format!("line {line} `{r}`"),
+        None => format!("`{r}`"),
+    };
+
+    // Each line is parsed into its inverse followed by itself, so check each
+    // line as written before its inverse
+    let mut order = (0..puzzle.relations.len()).collect::<Vec<_>>();
+    for chunk in order.chunk_by_mut(|&a, &b| {
+        let line = puzzle.relations[a].line;
+        line.is_some() && line == puzzle.relations[b].line
+    }) {
+        chunk.reverse();
+    }
+
+    for i in order {
+        let r = &puzzle.relations[i];
+        let lhs = &r.grip_to_replace;
+
+        if let Some(m) = lhs.transform.first()
+            && puzzle.commute(&m.quantum.family, &lhs.grip_name)
+        {
+            let message = format!("{m} commutes with {} and is ignored", lhs.grip_name);
+            report(IssueKind::Unreachable, r, message);
+            continue;
+        }
+
+        let mut is_shadowed = false;
+        for earlier in &puzzle.relations[..i] {
+            if earlier.line.is_some() && earlier.line == r.line {
+                continue; // the other direction of the same line
+            }
+            let Some(rewritten) = earlier.apply(lhs, puzzle) else {
+                continue;
+            };
+            if earlier.grip_to_replace != *lhs {
+                let message = format!("{} always applies first", describe(earlier));
+                report(IssueKind::Unreachable, r, message);
+            } else if rewritten.normalize(puzzle)? != r.new_grip.clone().normalize(puzzle)? {
+                let message = format!("{lhs} is rewritten differently by {}", describe(earlier));
+                report(IssueKind::Contradictory, r, message);
+            } else if earlier.new_grip == r.new_grip {
+                let message = format!("duplicates {}", describe(earlier));
+                report(IssueKind::Redundant, r, message);
+            } else {
+                let message = format!("{lhs} is already rewritten by {}", describe(earlier));
+                report(IssueKind::Redundant, r, message);
+            }
+            is_shadowed = true;
+            break;
+        }
+        if is_shadowed {
+            continue;
+        }
+
+        // Check whether the relations from other lines already rewrite `lhs`
+        // to the same grip as `r`. Relations that replace the same grip were
+        // checked above.
+        let is_other = |other: &Relation| {
+            (other.line.is_none() || other.line != r.line) && other.grip_to_replace != *lhs
+        };
+        let lhs_result = lhs.clone().normalize_with(puzzle, is_other)?;
+        let rhs_result = r.new_grip.clone().normalize_with(puzzle, is_other)?;
+        if lhs_result != *lhs && lhs_result == rhs_result {
+            report(
+                IssueKind::Redundant,
+                r,
+                "follows from the other relations".to_string(),
+            );
+        }
+    }
+
+    Ok(ret)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn issues(relations: &str) -> Vec<(IssueKind, Option<usize>)> {
+        let puzzle: Puzzle = relations.parse().unwrap();
+        let issues = check_consistency(&puzzle).unwrap();
+        issues.iter().map(|i| (i.kind, i.relation.line)).collect()
+    }
+
+    #[test]
+    fn contradictory_line() {
+        assert_eq!(
+            issues("U = F * R\nB = F * R"),
+            [(IssueKind::Contradictory, Some(2))],
+        );
+    }
+
+    #[test]
+    fn duplicate_line() {
+        assert_eq!(
+            issues("U = F * R\nU = F * R"),
+            [(IssueKind::Redundant, Some(2))],
+        );
+    }
+
+    #[test]
+    fn consistent_lines() {
+        assert!(issues("U = F * R\nR = U * F").is_empty());
+    }
+}
@@ -0,0 +1,133 @@
+use std::{collections::BTreeMap, fmt, str::FromStr};
+
+use crate::{MoveSeq, Region, validate_grip_name};
+
+/// Constraint on which grips a piece can be in at the same time.
+///
+/// Constraints hold for grips with the same transform, so `U # D` also means
+/// that no piece is in both `U × (R)` and `D × (R)`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Constraint {
+    /// No piece is in more than `max` of the grips, such as `at most 3 of U D
+    /// F B R L`. `U # D` is short for `at most 1 of U D`.
+    AtMost { max: usize, grip_names: Vec<String> },
+    /// Every piece is in at least `min` of the grips, such as `at least 1 of x`.
+    AtLeast { min: usize, grip_names: Vec<String> },
+    /// Every piece in `grip_name` is also in `supergrip_name`, such as `u < U`.
+    Subgrip {
+        grip_name: String,
+        supergrip_name: String,
+    },
+}
+impl fmt::Display for Constraint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::AtMost { max: 1, grip_names } => write!(f, "{}", grip_names.join(" # ")),
+            Self::AtMost { max, grip_names } => {
+                write!(f, "at most {max} of {}", grip_names.join(" "))
+            }
+            Self::AtLeast { min, grip_names } => {
+                write!(f, "at least {min} of {}", grip_names.join(" "))
+            }
+            Self::Subgrip {
+                grip_name,
+                supergrip_name,
+            } => write!(f, "{grip_name} < {supergrip_name}"),
+        }
+    }
+}
+impl FromStr for Constraint {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let parse_grip_names = |names: &str| -> Result<Vec<String>, String> {
+            let grip_names = names
+                .split(|c: char| c == ',' || c.is_whitespace())
+                .filter(|name| !name.is_empty())
+                .map(|name| validate_grip_name(name).map(|()| name.to_owned()))
+                .collect::<Result<Vec<_>, _>>()?;
+            if grip_names.is_empty() {
+                return Err(format!("constraint {s:?} has no grips"));
+            }
+            Ok(grip_names)
+        };
+        let parse_count = |rest: &str| -> Result<(usize, Vec<String>), String> {
+            let (count, grip_names) = rest
+                .split_once(" of ")
+                .ok_or_else(|| format!("constraint {s:?} is missing 'of'"))?;
+            let count = count
+                .trim()
+                .parse()
+                .map_err(|_| format!("invalid count {:?} in constraint {s:?}", count.trim()))?;
+            Ok((count, parse_grip_names(grip_names)?))
+        };
+
+        if let Some(rest) = s.strip_prefix("at most ") {
+            let (max, grip_names) = parse_count(rest)?;
+            Ok(Self::AtMost { max, grip_names })
+        } else if let Some(rest) = s.strip_prefix("at least ") {
+            let (min, grip_names) = parse_count(rest)?;
+            Ok(Self::AtLeast { min, grip_names })
+        } else if let Some((lhs, rhs)) = s.split_once('<') {
+            let (grip_name, supergrip_name) = (lhs.trim(), rhs.trim());
+            validate_grip_name(grip_name)?;
+            validate_grip_name(supergrip_name)?;
+            Ok(Self::Subgrip {
+                grip_name: grip_name.to_owned(),
+                supergrip_name: supergrip_name.to_owned(),
+            })
+        } else if s.contains('#') {
+            let grip_names = parse_grip_names(&s.replace('#', " "))?;
+            Ok(Self::AtMost { max: 1, grip_names })
+        } else {
+            Err(format!("invalid constraint {s:?}"))
+        }
+    }
+}
+impl Constraint {
+    /// Returns whether a region can contain any pieces according to the
+    /// constraint.
+    pub fn allows(&self, region: &Region) -> bool {
+        // Grip names included in and excluded from the region, by transform
+        let mut by_transform = BTreeMap::<&MoveSeq, (Vec<&str>, Vec<&str>)>::new();
+        for g in &region.include {
+            let entry = by_transform.entry(&g.transform).or_default();
+            entry.0.push(&g.grip_name);
+        }
+        for g in &region.exclude {
+            let entry = by_transform.entry(&g.transform).or_default();
+            entry.1.push(&g.grip_name);
+        }
+
+        by_transform.values().all(|(include, exclude)| {
+            let count = |names: &[&str], grip_names: &[String]| {
+                grip_names
+                    .iter()
+                    .filter(|g| names.contains(&g.as_str()))
+                    .count()
+            };
+            match self {
+                Self::AtMost { max, grip_names } => count(include, grip_names) <= *max,
+                Self::AtLeast { min, grip_names } => {
+                    grip_names.len() - count(exclude, grip_names) >= *min
+                }
+                Self::Subgrip {
+                    grip_name,
+                    supergrip_name,
+                } => {
+                    !(include.contains(&grip_name.as_str())
+                        && exclude.contains(&supergrip_name.as_str()))
+                }
+            }
+        })
+    }
+}
+
+/// Returns whether a line of relations is a constraint.
+pub(crate) fn is_constraint(line: &str) -> bool {
+    line.starts_with("at most ")
+        || line.starts_with("at least ")
+        || line.contains('#')
+        || line.contains('<')
+}
@@ -0,0 +1,182 @@
+use std::fmt;
+
+use cubing::alg::{Move, MovePrefix};
+use itertools::Itertools;
+use serde::Serialize;
+
+use crate::{MoveSeq, Puzzle};
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize)]
+pub struct Grip {
+    #[serde(rename = "name")]
+    pub grip_name: String,
+    pub transform: MoveSeq,
+}
+impl fmt::Display for Grip {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let Self {
+            grip_name,
+            transform,
+        } = self;
+
+        if self.transform.is_empty() {
+            write!(f, "{grip_name}")
+        } else {
+            write!(f, "{grip_name} × ({transform})")
+        }
+    }
+}
+impl Grip {
+    pub fn new(grip_name: String) -> Self {
+        Self {
+            grip_name,
+            transform: MoveSeq::new(),
+        }
+    }
+    /// Applies a move to the grip and then applies relations until none
+    /// match.
+    ///
+    /// Returns an error if the relations rewrite the grip forever.
+    pub fn do_move(mut self, m: Move, puzzle: &Puzzle) -> Result<Self, String> {
+        self.transform.push_back(m, puzzle);
+        self.normalize(puzzle)
+    }
+    /// Applies relations to the grip until none match.
+    ///
+    /// Returns an error if the relations rewrite the grip forever.
+    pub fn normalize(self, puzzle: &Puzzle) -> Result<Self, String> {
+        self.normalize_with(puzzle, |_| true)
+    }
+    /// Applies the relations for which `is_enabled` returns `true` to the grip
+    /// until none match.
+    pub(crate) fn normalize_with(
+        mut self,
+        puzzle: &Puzzle,
+        is_enabled: impl Fn(&Relation) -> bool,
+    ) -> Result<Self, String> {
+        let mut seen = vec![];
+        'rewrite: loop {
+            // Grip is not affected by moves on its own axis or moves that
+            // commute with it
+            self.transform
+                .pop_front_while(|m| puzzle.commute(&m.quantum.family, &self.grip_name));
+
+            if seen.contains(&self) {
+                let cycle = seen.iter().skip_while(|g| **g != self).join(" → ");
+                return Err(format!("relations rewrite grips forever: {cycle} → {self}"));
+            }
+            seen.push(self.clone());
+
+            // Apply the first relation that matches and start over, since it
+            // may enable an earlier relation
+            for r in puzzle.relations.iter().filter(|r| is_enabled(r)) {
+                if let Some(new) = r.apply(&self, puzzle) {
+                    self = new;
+                    continue 'rewrite;
+                }
+            }
+            return Ok(self);
+        }
+    }
+}
+
+/// Rewriting rule that replaces [`Relation::grip_to_replace`] with
+/// [`Relation::new_grip`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Relation {
+    pub new_grip: Grip,
+    pub grip_to_replace: Grip,
+    /// Line of the relations that the relation was parsed from, starting at 1.
+    pub line: Option<usize>,
+}
+impl fmt::Display for Relation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let Self {
+            new_grip,
+            grip_to_replace,
+            line: _,
+        } = self;
+
+        if new_grip.transform.is_empty() {
+            let Grip {
+                grip_name,
+                transform,
+            } = grip_to_replace;
+            if transform.is_empty() {
+                write!(f, "{new_grip} = {grip_name} *")
+            } else {
+                write!(f, "{new_grip} = {grip_name} * {transform}")
+            }
+        } else {
+            write!(f, "{new_grip} = {grip_to_replace}")
+        }
+    }
+}
+impl Relation {
+    /// Returns the grip rewritten by the relation, if the relation matches.
+    ///
+    /// The relation matches if the grip has the same name as
+    /// [`Relation::grip_to_replace`] and its transform starts with that of
+    /// `grip_to_replace`. The rest of the transform is kept after the transform
+    /// of [`Relation::new_grip`].
+    pub fn apply(&self, grip: &Grip, puzzle: &Puzzle) -> Option<Grip> {
+        if grip.grip_name != self.grip_to_replace.grip_name {
+            return None;
+        }
+        let mut rest = grip.transform.clone();
+        if !rest.pop_front_if_matches(&self.grip_to_replace.transform) {
+            return None;
+        }
+        let mut ret = self.new_grip.clone();
+        for m in rest.iter() {
+            ret.transform.push_back(m.clone(), puzzle);
+        }
+        Some(ret)
+    }
+}
+
+/// Returns the names of the layer grips affected by a move. Layer 1 is named
+/// after the move family, and deeper layers have the layer number as a prefix,
+/// so `R` affects `R`, `2R` affects `2R`, `Rw` affects `R` and `2R`, and
+/// `2-3Rw` affects `2R` and `3R`.
+pub fn move_grip_names(m: &Move) -> Vec<String> {
+    let family = &m.quantum.family;
+    let (axis, wide) = match family.strip_suffix('w') {
+        Some(axis) if !axis.is_empty() => (axis, true),
+        _ => (family.as_str(), false),
+    };
+    let layers = match &m.quantum.prefix {
+        None if wide => 1..=2,
+        None => 1..=1,
+        Some(MovePrefix::Layer(l)) if wide => 1..=l.layer,
+        Some(MovePrefix::Layer(l)) => l.layer..=l.layer,
+        Some(MovePrefix::Range(r)) => r.outer_layer..=r.inner_layer,
+    };
+    layers
+        .map(|layer| match layer {
+            1 => axis.to_owned(),
+            _ => format!("{layer}{axis}"),
+        })
+        .collect()
+}
+
+/// Returns the axis of a grip name or move family, which is shared by all
+/// layers of a face. For example, `R`, `2R`, and `Rw` all have the axis `R`.
+pub fn grip_axis(s: &str) -> &str {
+    let s = s.trim_start_matches(|c: char| c.is_ascii_digit());
+    match s.strip_suffix('w') {
+        Some(axis) if !axis.is_empty() => axis,
+        _ => s,
+    }
+}
+
+/// Returns an error unless `s` is a grip name: letters or `_`, optionally after
+/// a layer number, such as `U`, `2R`, `BR`, `DRB`, or `u`.
+pub fn validate_grip_name(s: &str) -> Result<(), String> {
+    let name = s.trim_start_matches(|c: char| c.is_ascii_digit());
+    if !name.is_empty() && name.chars().all(|c| c.is_alphabetic() || c == '_') {
+        Ok(())
+    } else {
+        Err(format!("invalid grip {s:?}"))
+    }
+}
@@ -0,0 +1,351 @@
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    str::FromStr,
+    sync::Arc,
+};
+
+use cubing::{
+    alg::{Move, QuantumMove},
+    kpuzzle::{KPuzzle, KTransformation},
+};
+use itertools::Itertools;
+
+use crate::{Grip, Region, presets::twist_relations};
+
+/// Maximum order of a move considered when deriving relations.
+const MAX_ORDER: usize = 360;
+
+/// Position of a piece on a puzzle: the index of its orbit and its index
+/// within that orbit.
+pub type Position = (usize, u8);
+
+/// Loads one of the KPuzzles built into `cubing` (`3x3x3` or `2x2x2`) or a
+/// KPuzzle definition from a JSON file.
+pub fn load_kpuzzle(name_or_path: &str) -> Result<KPuzzle, String> {
+    match name_or_path {
+        "3x3x3" => Ok(cubing::puzzles::cube3x3x3_kpuzzle().clone()),
+        "2x2x2" => Ok(cubing::puzzles::cube2x2x2_kpuzzle().clone()),
+        path => {
+            let bytes = std::fs::read(path).map_err(|e| format!("error reading {path:?}: {e}"))?;
+            KPuzzle::try_from_json(&bytes).map_err(|e| format!("invalid KPuzzle {path:?}: {e}"))
+        }
+    }
+}
+
+/// Move of a KPuzzle without a layer prefix.
+struct KMove {
+    family: String,
+    /// Transformations of the move with amounts from 1 to `order`.
+    powers: Vec<KTransformation>,
+    support: BTreeSet<Position>,
+    order: i32,
+}
+
+/// Derives relations from a KPuzzle definition, including grip orders,
+/// commutations, and constraints between grips with disjoint or nested pieces.
+///
+/// Every move without a layer prefix is a grip, except for moves that affect
+/// every piece, which are whole-puzzle rotations. Two grips that have the same
+/// pieces in common with a move are related by that move: if turning `H` takes
+/// the pieces in both `G` and `H` to the pieces in both `G'` and `H`, then
+/// `G' = G * H`.
+pub fn derive_relations(kpuzzle: &KPuzzle) -> Result<String, String> {
+    let def = kpuzzle.definition();
+    // Directly defined moves take precedence over derived moves, and shorter
+    // names take precedence over longer ones
+    let key = |m: &&Move| (m.quantum.family.len(), m.quantum.family.clone());
+    let families = itertools::chain(
+        def.moves.keys().sorted_by_key(key),
+        def.derived_moves
+            .iter()
+            .flat_map(|m| m.keys().sorted_by_key(key)),
+    )
+    .filter(|m| m.quantum.prefix.is_none())
+    .map(|m| m.quantum.family.clone());
+
+    let identity = kpuzzle.identity_transformation();
+    let all_positions = all_positions(kpuzzle);
+
+    let mut kmoves: Vec<KMove> = vec![];
+    for family in families {
+        let transformation = kpuzzle
+            .transformation_from_move(&make_move(&family, 1))
+            .map_err(|e| e.to_string())?;
+        // Skip aliases, such as `Uw` for `u` and `Dv` for `y'`
+        if transformation == identity || kmoves.iter().any(|k| k.powers.contains(&transformation)) {
+            continue;
+        }
+        let mut powers = vec![transformation.clone()];
+        while powers.last() != Some(&identity) {
+            if powers.len() >= MAX_ORDER {
+                return Err(format!("move {family} has order greater than {MAX_ORDER}"));
+            }
+            powers.push(powers.last().unwrap().apply_transformation(&transformation));
+        }
+        kmoves.push(KMove {
+            support: support(kpuzzle, &transformation),
+            family,
+            order: powers.len() as i32,
+            powers,
+        });
+    }
+    // Moves that move every piece rotate the whole puzzle
+    let (rotations, grips): (Vec<_>, Vec<_>) =
+        kmoves.iter().partition(|k| k.support == all_positions);
+
+    let mut out = String::new();
+
+    for k in &kmoves {
+        out += &format!("{}: {}\n", k.family, k.order);
+    }
+    if !rotations.is_empty() {
+        out += &format!(
+            "rotation {}\n",
+            rotations.iter().map(|k| &k.family).join(" ")
+        );
+    }
+    out += "\n";
+
+    let mut commuting = BTreeSet::new();
+    for (a, b) in kmoves.iter().tuple_combinations() {
+        let ab = a.powers[0].apply_transformation(&b.powers[0]);
+        let ba = b.powers[0].apply_transformation(&a.powers[0]);
+        if ab == ba {
+            out += &format!("{} | {}\n", a.family, b.family);
+            commuting.insert((&a.family, &b.family));
+            commuting.insert((&b.family, &a.family));
+        }
+    }
+    out += "\n";
+
+    for (a, b) in grips.iter().tuple_combinations() {
+        if a.support.is_disjoint(&b.support) {
+            out += &format!("{} # {}\n", a.family, b.family);
+        } else if a.support.is_subset(&b.support) {
+            out += &format!("{} < {}\n", a.family, b.family);
+        } else if b.support.is_subset(&a.support) {
+            out += &format!("{} < {}\n", b.family, a.family);
+        }
+    }
+    out += "\n";
+
+    out += &twist_relations(
+        &kmoves,
+        |h| (h.family.as_str(), h.order),
+        |h, amount| {
+            let t = &h.powers[amount as usize - 1];
+            grips
+                .iter()
+                .filter(|g| {
+                    // Handled by commutation
+                    g.family != h.family && !commuting.contains(&(&g.family, &h.family))
+                })
+                .filter_map(|g| {
+                    let common = &g.support & &h.support;
+                    if common.is_empty() {
+                        return None;
+                    }
+                    let target = image(kpuzzle, t, &common);
+                    let new = grips
+                        .iter()
+                        .filter(|new| &new.support & &h.support == target)
+                        .min_by_key(|new| (new.support.len() != g.support.len(), &new.family))?;
+                    Some((g.family.as_str(), new.family.as_str()))
+                })
+                .collect()
+        },
+    );
+
+    Ok(out)
+}
+
+fn make_move(family: &str, amount: i32) -> Move {
+    Move {
+        quantum: Arc::new(QuantumMove::new(family, None)),
+        amount,
+    }
+}
+
+/// Returns every piece position on a puzzle.
+pub fn all_positions(kpuzzle: &KPuzzle) -> BTreeSet<Position> {
+    kpuzzle
+        .orbit_info_iter()
+        .enumerate()
+        .flat_map(|(o, orbit)| (0..orbit.num_pieces).map(move |i| (o, i)))
+        .collect()
+}
+
+/// Faces of a cube, in the order they appear in piece names such as `UFR`.
+const CUBE_FACES: &[&str] = &["U", "D", "F", "B", "R", "L"];
+
+/// Returns a name for every piece, such as `UFR corner`, made of the faces
+/// that move the piece and the name of its orbit.
+///
+/// If the KPuzzle has the moves of a cube, those are the faces. Otherwise the
+/// faces are the moves without a layer prefix that do not move every piece or
+/// every piece of another move. Pieces that would otherwise have no name or
+/// share a name are numbered instead.
+pub fn piece_names(kpuzzle: &KPuzzle) -> BTreeMap<Position, String> {
+    let all_positions = all_positions(kpuzzle);
+    let def = kpuzzle.definition();
+    let moves = itertools::chain(
+        def.moves.keys(),
+        def.derived_moves.iter().flat_map(|m| m.keys()),
+    )
+    .filter(|m| m.quantum.prefix.is_none())
+    .filter_map(|m| {
+        let t = kpuzzle.transformation_from_move(m).ok()?;
+        let support = support(kpuzzle, &t);
+        (support != all_positions).then(|| (m.quantum.family.clone(), support))
+    })
+    .collect_vec();
+    let is_cube = CUBE_FACES
+        .iter()
+        .all(|&face| moves.iter().any(|(family, _)| family == face));
+    let faces = moves
+        .iter()
+        .filter(|(family, support)| match is_cube {
+            true => CUBE_FACES.contains(&family.as_str()),
+            // Skip wide moves
+            false => !moves
+                .iter()
+                .any(|(_, other)| other.len() < support.len() && other.is_subset(support)),
+        })
+        .sorted_by_key(|(family, _)| {
+            let index = CUBE_FACES.iter().position(|f| f == family);
+            (index.unwrap_or(usize::MAX), family.clone())
+        })
+        .collect_vec();
+    let orbit_names = kpuzzle
+        .orbit_info_iter()
+        .map(|orbit| {
+            let name = orbit.name.to_string().to_lowercase();
+            match name.strip_suffix('s') {
+                Some(singular) => singular.to_owned(),
+                None => name,
+            }
+        })
+        .collect_vec();
+
+    all_positions
+        .iter()
+        .map(|&(orbit, i)| {
+            let faces = faces
+                .iter()
+                .filter(|(_, support)| support.contains(&(orbit, i)))
+                .map(|(family, _)| family)
+                .join("");
+            (orbit, i, faces)
+        })
+        .into_group_map_by(|(orbit, _, faces)| (*orbit, faces.clone()))
+        .into_values()
+        .flat_map(|pieces| {
+            let orbit_names = &orbit_names;
+            let is_unique = pieces.len() == 1;
+            pieces.into_iter().map(move |(orbit, i, faces)| {
+                let name = match (faces.is_empty(), is_unique) {
+                    (false, true) => format!("{faces} {}", orbit_names[orbit]),
+                    (false, false) => format!("{faces} {} {i}", orbit_names[orbit]),
+                    (true, _) => format!("{} {i}", orbit_names[orbit]),
+                };
+                ((orbit, i), name)
+            })
+        })
+        .collect()
+}
+
+/// Returns the pieces in each region.
+///
+/// Each grip contains the pieces moved by the KPuzzle move of the same name,
+/// and `G × (T)` contains the pieces that are in `G` after `T`.
+pub fn region_pieces<'a>(
+    kpuzzle: &KPuzzle,
+    regions: impl IntoIterator<Item = &'a Region>,
+) -> Result<BTreeMap<&'a Region, BTreeSet<Position>>, String> {
+    let mut grips = GripPositions::new(kpuzzle);
+    let all_positions = all_positions(kpuzzle);
+    let mut ret = BTreeMap::new();
+    for r in regions {
+        let mut pieces = all_positions.clone();
+        for g in &r.include {
+            pieces = &pieces & &grips.positions(g)?;
+        }
+        for g in &r.exclude {
+            pieces = &pieces - &grips.positions(g)?;
+        }
+        ret.insert(r, pieces);
+    }
+    Ok(ret)
+}
+
+/// Cache of the pieces moved by each grip.
+pub(crate) struct GripPositions<'a> {
+    kpuzzle: &'a KPuzzle,
+    supports: HashMap<String, BTreeSet<Position>>,
+}
+impl<'a> GripPositions<'a> {
+    pub fn new(kpuzzle: &'a KPuzzle) -> Self {
+        Self {
+            kpuzzle,
+            supports: HashMap::new(),
+        }
+    }
+
+    /// Returns the pieces moved by the KPuzzle move named after a grip.
+    pub fn support(&mut self, grip_name: &str) -> Result<&BTreeSet<Position>, String> {
+        if !self.supports.contains_key(grip_name) {
+            let t = Move::from_str(grip_name)
+                .ok()
+                .and_then(|m| self.kpuzzle.transformation_from_move(&m).ok())
+                .ok_or_else(|| format!("grip {grip_name} is not a move of the KPuzzle"))?;
+            let support = support(self.kpuzzle, &t);
+            self.supports.insert(grip_name.to_owned(), support);
+        }
+        Ok(&self.supports[grip_name])
+    }
+
+    /// Returns the pieces in a grip after its transform.
+    pub fn positions(&mut self, grip: &Grip) -> Result<BTreeSet<Position>, String> {
+        let mut ret = self.support(&grip.grip_name)?.clone();
+        for m in grip.transform.iter() {
+            let t = self
+                .kpuzzle
+                .transformation_from_move(m)
+                .map_err(|e| format!("move {m} is not a move of the KPuzzle: {e}"))?;
+            ret = image(self.kpuzzle, &t, &ret);
+        }
+        Ok(ret)
+    }
+}
+
+/// Returns the positions of pieces that are moved or reoriented by a
+/// transformation.
+pub fn support(kpuzzle: &KPuzzle, t: &KTransformation) -> BTreeSet<Position> {
+    let mut ret = BTreeSet::new();
+    for (o, orbit) in kpuzzle.orbit_info_iter().enumerate() {
+        for i in 0..orbit.num_pieces {
+            if t.get_permutation_idx(orbit, i) != i || t.get_orientation_delta(orbit, i) != 0 {
+                ret.insert((o, i));
+            }
+        }
+    }
+    ret
+}
+
+/// Returns the positions that the pieces at `positions` are moved to by a
+/// transformation.
+pub fn image(
+    kpuzzle: &KPuzzle,
+    t: &KTransformation,
+    positions: &BTreeSet<Position>,
+) -> BTreeSet<Position> {
+    let mut ret = BTreeSet::new();
+    for (o, orbit) in kpuzzle.orbit_info_iter().enumerate() {
+        for i in 0..orbit.num_pieces {
+            if positions.contains(&(o, t.get_permutation_idx(orbit, i))) {
+                ret.insert((o, i));
+            }
+        }
+    }
+    ret
+}
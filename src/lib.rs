@@ -0,0 +1,47 @@
+//! Grip theoretic twisty puzzle algorithm analysis.
+
+mod analysis;
+mod confluence;
+mod consistency;
+mod constraint;
+mod grip;
+mod kpuzzle;
+mod moveseq;
+mod parse;
+mod presets;
+mod puzzle;
+mod region;
+mod session;
+mod symmetry;
+mod verify;
+
+pub use analysis::{
+    Analysis, JSON_FORMAT_VERSION, Step, analyze, analyze_with_setup, step_through,
+};
+pub use confluence::{CriticalPair, complete, critical_pairs};
+pub use consistency::{Issue, IssueKind, check_consistency};
+pub use constraint::Constraint;
+pub use grip::{Grip, Relation, grip_axis, move_grip_names, validate_grip_name};
+pub use kpuzzle::{Position, derive_relations, load_kpuzzle, piece_names, region_pieces};
+pub use moveseq::MoveSeq;
+pub use parse::{ParseError, parse_alg};
+pub use presets::Preset;
+pub use puzzle::Puzzle;
+pub use region::Region;
+pub use session::Session;
+pub use symmetry::Symmetry;
+pub use verify::{Verification, verify};
+
+/// Relations for the U, R, F, and L faces of a 3x3x3.
+pub const DEFAULT_RELATIONS: &str = "\
+    U: 4\n\
+    R: 4\n\
+    F: 4\n\
+    L: 4\n\
+    R | L\n\
+    U = F * R\n\
+    R = U * F\n\
+    F = R * U\n\
+    L = F * U\n\
+    F = U * L\n\
+";
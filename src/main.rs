@@ -1,176 +1,302 @@
-use core::fmt;
-use std::{
-    collections::{BTreeMap, BTreeSet},
-    str::FromStr,
+use std::{collections::BTreeMap, path::Path, sync::Arc};
+
+use cubing::kpuzzle::KPuzzle;
+use grippy::{
+    Analysis, CriticalPair, DEFAULT_RELATIONS, Grip, Issue, MoveSeq, ParseError, Preset, Puzzle,
+    Region, Relation, Session, Step, Verification, analyze_with_setup, check_consistency, complete,
+    critical_pairs, derive_relations, load_kpuzzle, parse_alg, piece_names, region_pieces,
+    step_through, verify,
 };
-
-use cubing::alg::{Alg, Move};
-
-mod moveseq;
-
-use itertools::Itertools;
-use moveseq::MoveSeq;
+use serde::{Deserialize, Serialize};
 
 const ZOOM: f32 = 1.5;
+const RELATIONS_MAX_HEIGHT: f32 = 200.0;
+const REPORT_MAX_HEIGHT: f32 = 100.0;
+const ERROR_UNDERLINE_WIDTH: f32 = 1.5;
 
 const DEFAULT_ALG: &str = "[R, U] [U2, R]";
-const DEFAULT_RELATIONS: &str = "\
-    U = F * R\n\
-    R = U * F\n\
-    F = R * U\n\
-    L = F * U\n\
-    F = U * L\n\
-";
+const DEFAULT_KPUZZLE: &str = "3x3x3";
+
+const SESSION_PATH_WIDTH: f32 = 200.0;
 
 const HOVERED_GRIP_INCLUDE_COLOR: egui::Color32 = egui::Color32::from_rgb(50, 160, 120);
 const HOVERED_GRIP_EXCLUDE_COLOR: egui::Color32 = egui::Color32::from_rgb(150, 100, 30);
 const HOVERED_REGION_COLOR: egui::Color32 = egui::Color32::from_rgb(10, 90, 255);
 
 fn main() -> eframe::Result {
+    // The session to open instead of the saved one, if a permalink is given
+    let permalink = std::env::args().nth(1).map(|s| Session::from_permalink(&s));
     eframe::run_native(
         "Grippy",
         eframe::NativeOptions::default(),
-        Box::new(|cc| Ok(Box::new(App::new(cc)))),
+        Box::new(|cc| Ok(Box::new(App::new(cc, permalink)))),
     )
 }
 
+/// State saved between runs.
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+struct Settings {
+    session: Session,
+    session_path: String,
+    kpuzzle: String,
+    verify: bool,
+    check_confluence: bool,
+    complete_relations: bool,
+}
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            session: Session {
+                setup: String::new(),
+                alg: DEFAULT_ALG.to_string(),
+                relations: DEFAULT_RELATIONS.to_string(),
+                preset: None,
+            },
+            session_path: String::new(),
+            kpuzzle: DEFAULT_KPUZZLE.to_string(),
+            verify: false,
+            check_confluence: false,
+            complete_relations: false,
+        }
+    }
+}
+
 #[derive(Default)]
 struct App {
+    /// File to open and save sessions from.
+    session_path: String,
+    session_error: Option<String>,
+    /// Permalink being pasted, if the paste dialog is open.
+    permalink_str: Option<String>,
+    permalink_error: Option<String>,
+
+    /// Moves applied before the algorithm.
+    setup_str: String,
+    setup_error: Option<ParseError>,
     alg_str: String,
-    alg_is_valid: bool,
-    moves: MoveSeq,
+    alg_error: Option<ParseError>,
 
+    preset: Option<Preset>,
+    kpuzzle_str: String,
+    kpuzzle_error: Option<String>,
+    /// KPuzzle to verify the analysis against, if verification is enabled.
+    verify_kpuzzle: Option<KPuzzle>,
     relations_str: String,
+    /// Errors in the relations text, which are underlined in the editor.
+    relations_errors: Vec<ParseError>,
+    /// Error from checking or analyzing the relations.
     relations_str_error: Option<String>,
-    relations: Vec<Relation>,
-
-    regions: BTreeSet<Region>,
-    grips: BTreeSet<Grip>,
-    results: BTreeMap<MoveSeq, BTreeMap<Vec<bool>, Vec<Region>>>,
+    /// Contradictory, redundant, and unreachable relations.
+    consistency_issues: Vec<Issue>,
+    check_confluence: bool,
+    complete_relations: bool,
+    critical_pairs: Option<Vec<CriticalPair>>,
+    added_relations: Option<Vec<Relation>>,
+    /// Relations generated by the symmetries of the relations.
+    generated_relations: Vec<Relation>,
+
+    analysis: Analysis,
+    /// Regions before each move and after the last move.
+    steps: Vec<Step>,
+    /// Number of moves done in the scrubber.
+    step: usize,
+    verification: Option<Result<Verification, String>>,
+    /// Names of the pieces in each region, if the preset has a KPuzzle.
+    region_pieces: Option<BTreeMap<Region, Vec<String>>>,
+    region_pieces_error: Option<String>,
 
     hovered_grip: Option<Grip>,
     hovered_region: Option<Region>,
 }
 impl App {
-    pub fn new(cc: &eframe::CreationContext) -> Self {
+    pub fn new(cc: &eframe::CreationContext, permalink: Option<Result<Session, String>>) -> Self {
         cc.egui_ctx.set_zoom_factor(ZOOM);
+        let settings: Settings = cc
+            .storage
+            .and_then(|storage| eframe::get_value(storage, eframe::APP_KEY))
+            .unwrap_or_default();
         let mut ret = Self {
-            alg_str: DEFAULT_ALG.to_string(),
-            relations_str: DEFAULT_RELATIONS.to_string(),
+            session_path: settings.session_path,
+            kpuzzle_str: settings.kpuzzle,
+            check_confluence: settings.check_confluence,
+            complete_relations: settings.complete_relations,
             ..Default::default()
         };
-        ret.recompute_everything();
+        if settings.verify {
+            match load_kpuzzle(&ret.kpuzzle_str) {
+                Ok(k) => ret.verify_kpuzzle = Some(k),
+                Err(e) => ret.kpuzzle_error = Some(e),
+            }
+        }
+        match permalink {
+            Some(Ok(session)) => ret.set_session(session),
+            Some(Err(e)) => {
+                ret.session_error = Some(e);
+                ret.set_session(settings.session);
+            }
+            None => ret.set_session(settings.session),
+        }
         ret
     }
 
+    fn session(&self) -> Session {
+        Session {
+            setup: self.setup_str.clone(),
+            alg: self.alg_str.clone(),
+            relations: self.relations_str.clone(),
+            preset: self.preset,
+        }
+    }
+    fn set_session(&mut self, session: Session) {
+        self.setup_str = session.setup;
+        self.alg_str = session.alg;
+        self.relations_str = session.relations;
+        self.preset = session.preset;
+        self.recompute_everything();
+    }
+
     fn recompute_everything(&mut self) {
+        self.relations_errors = vec![];
         self.relations_str_error = None;
+        self.consistency_issues = vec![];
+        self.critical_pairs = None;
+        self.added_relations = None;
+        self.generated_relations = vec![];
+        let mut puzzle = match Puzzle::from_str_in_dir(&self.relations_str, Path::new(".")) {
+            Ok(p) => {
+                self.generated_relations = p.generated.clone();
+                Some(p)
+            }
+            Err(errors) => {
+                self.relations_errors = errors;
+                None
+            }
+        };
+        if let Some(p) = &mut puzzle
+            && let Err(e) = self.check_relations(p)
+        {
+            self.relations_str_error = Some(e);
+            puzzle = None;
+        }
 
-        // Parse relations
-        self.relations = vec![];
-        for line in self.relations_str.lines() {
-            let line = line.trim();
-            if !line.is_empty() {
-                let Some((lhs, rhs)) = line.split_once("=") else {
-                    self.relations_str_error =
-                        Some(format!("relation line {line:?} is missing '='"));
-                    break;
-                };
-                let new_grip_name = lhs.trim().to_owned();
-                if let Err(e) = validate_grip_name(&new_grip_name) {
-                    self.relations_str_error = Some(e);
-                    break;
+        let alg = match Session::from_alg_url(&self.alg_str) {
+            Some(Err(message)) => Err(ParseError {
+                line: 1,
+                columns: 1..self.alg_str.chars().count() + 1,
+                message,
+            }),
+            _ => parse_alg(&self.alg_str),
+        };
+        self.alg_error = alg.as_ref().err().cloned();
+        let setup = parse_alg(&self.setup_str);
+        self.setup_error = setup.as_ref().err().cloned();
+
+        self.verification = None;
+        self.steps = vec![];
+        self.analysis = match (setup, alg, puzzle) {
+            (Ok(setup), Ok(alg), Some(puzzle)) => match analyze_with_setup(&setup, &alg, &puzzle) {
+                Ok(analysis) => {
+                    if let Some(kpuzzle) = &self.verify_kpuzzle {
+                        self.verification = Some(verify(&analysis, &puzzle, kpuzzle));
+                    }
+                    match step_through(&analysis, &puzzle) {
+                        Ok(steps) => self.steps = steps,
+                        Err(e) => self.relations_str_error = Some(e),
+                    }
+                    analysis
                 }
-
-                let Some((rhs1, rhs2)) =
-                    Option::or_else(rhs.trim().split_once('*'), || rhs.trim().split_once('×'))
-                else {
-                    self.relations_str_error =
-                        Some(format!("relation line {line:?} is missing '*' or '×'"));
-                    break;
-                };
-                let old_grip_name = rhs1.trim().to_owned();
-                if let Err(e) = validate_grip_name(&old_grip_name) {
+                // The relations are at fault
+                Err(e) => {
                     self.relations_str_error = Some(e);
-                    break;
-                }
-
-                match Alg::from_str(rhs2) {
-                    Ok(alg) => {
-                        // Add inverse relation
-                        self.relations.push(Relation {
-                            new_grip_name: old_grip_name.clone(),
-                            grip_to_replace: Grip {
-                                grip_name: new_grip_name.clone(),
-                                transform: MoveSeq::from_alg(&alg.invert()),
-                            },
-                        });
-                        // Add original relation
-                        self.relations.push(Relation {
-                            new_grip_name,
-                            grip_to_replace: Grip {
-                                grip_name: old_grip_name,
-                                transform: MoveSeq::from_alg(&alg),
-                            },
-                        });
+                    Analysis {
+                        setup: MoveSeq::from_alg_in_order(&setup, &puzzle),
+                        moves: MoveSeq::from_alg_in_order(&alg, &puzzle),
+                        ..Default::default()
                     }
-                    Err(e) => self.relations_str_error = Some(e.to_string()),
                 }
+            },
+            (Ok(setup), Ok(alg), None) => Analysis {
+                setup: MoveSeq::from_alg_in_order(&setup, &Puzzle::default()),
+                moves: MoveSeq::from_alg_in_order(&alg, &Puzzle::default()),
+                ..Default::default()
+            },
+            _ => Analysis::default(),
+        };
+        self.step = self.step.min(self.steps.len().saturating_sub(1));
+
+        self.region_pieces = None;
+        self.region_pieces_error = None;
+        if let Some(kpuzzle) = self.preset.and_then(|p| p.kpuzzle()) {
+            let names = piece_names(&kpuzzle);
+            match region_pieces(&kpuzzle, &self.analysis.regions) {
+                Ok(pieces) => {
+                    let pieces = pieces
+                        .into_iter()
+                        .map(|(r, pieces)| {
+                            (r.clone(), pieces.iter().map(|p| names[p].clone()).collect())
+                        })
+                        .collect();
+                    self.region_pieces = Some(pieces);
+                }
+                Err(e) => self.region_pieces_error = Some(e),
             }
         }
+    }
 
-        // Parse alg
-        let alg_result = Alg::from_str(&self.alg_str);
-        self.alg_is_valid = alg_result.is_ok();
-
-        self.grips.clear();
-        self.regions.clear();
-        self.results.clear();
-
-        if !self.alg_is_valid || self.relations_str_error.is_some() {
-            return;
+    /// Checks the consistency of the relations, and checks confluence of the
+    /// relations and completes them, if enabled.
+    fn check_relations(&mut self, puzzle: &mut Puzzle) -> Result<(), String> {
+        self.consistency_issues = check_consistency(puzzle)?;
+        if self.check_confluence {
+            self.critical_pairs = Some(critical_pairs(puzzle)?);
         }
+        if self.complete_relations {
+            self.added_relations = Some(complete(puzzle)?);
+        }
+        Ok(())
+    }
 
-        if let Ok(alg) = alg_result {
-            self.moves = MoveSeq::from_alg(&alg);
-
-            let inverse_moves = MoveSeq::from_alg(&alg.invert());
-            self.regions = BTreeSet::from_iter([Region::default()]);
-            for m in inverse_moves.iter() {
-                self.regions = std::mem::take(&mut self.regions)
-                    .into_iter()
-                    .flat_map(|r| r.do_move(m.clone(), &self.relations))
-                    .flatten()
-                    .collect();
-            }
-
-            self.grips = self
-                .regions
-                .iter()
-                .flat_map(|r| itertools::chain(&r.include, &r.exclude))
-                .cloned()
-                .collect();
-
-            for region in &self.regions {
-                let mut move_seq = MoveSeq::new();
-                let mut move_mask = vec![];
-                let mut r = region.clone();
-                for m in self.moves.iter() {
-                    let [not_affected, affected] = r.do_move(m.clone(), &self.relations);
-                    move_mask.push(affected.is_some());
-                    if affected.is_some() {
-                        move_seq.push_back(m.clone());
+    /// Shows the dialog for pasting a permalink, if it is open.
+    fn show_permalink_dialog(&mut self, ctx: &egui::Context) {
+        let Some(permalink_str) = &mut self.permalink_str else {
+            return;
+        };
+        let mut is_open = true;
+        let mut session = None;
+        egui::Window::new("Paste permalink")
+            .open(&mut is_open)
+            .collapsible(false)
+            .show(ctx, |ui| {
+                ui.add(
+                    egui::TextEdit::singleline(permalink_str)
+                        .hint_text("alg=R+U+R%27+U%27&preset=3x3x3"),
+                );
+                if ui.button("Open").clicked() {
+                    match Session::from_permalink(permalink_str) {
+                        Ok(s) => session = Some(s),
+                        Err(e) => self.permalink_error = Some(e),
                     }
-                    r = affected.or(not_affected).unwrap();
                 }
-                self.results
-                    .entry(move_seq)
-                    .or_default()
-                    .entry(move_mask)
-                    .or_default()
-                    .push(region.clone());
-            }
+                if let Some(e) = &self.permalink_error {
+                    ui.colored_label(ui.visuals().error_fg_color, e);
+                }
+            });
+        if let Some(session) = session {
+            self.set_session(session);
+            is_open = false;
         }
+        if !is_open {
+            self.permalink_str = None;
+        }
+    }
+
+    /// Returns whether a region contains any pieces, or `true` if the pieces
+    /// are unknown.
+    fn is_region_visible(&self, region: &Region) -> bool {
+        self.region_pieces
+            .as_ref()
+            .is_none_or(|pieces| pieces.get(region).is_some_and(|p| !p.is_empty()))
     }
 
     fn display_grip(
@@ -224,50 +350,322 @@ impl App {
                 }
             }
             ui.colored_label(color, "}");
+            if let Some(pieces) = self.region_pieces.as_ref().and_then(|p| p.get(region)) {
+                ui.weak(format!("  ({}: {})", pieces.len(), pieces.join(", ")));
+            }
         })
         .response
     }
 }
 impl eframe::App for App {
+    fn save(&mut self, storage: &mut dyn eframe::Storage) {
+        let settings = Settings {
+            session: self.session(),
+            session_path: self.session_path.clone(),
+            kpuzzle: self.kpuzzle_str.clone(),
+            verify: self.verify_kpuzzle.is_some(),
+            check_confluence: self.check_confluence,
+            complete_relations: self.complete_relations,
+        };
+        eframe::set_value(storage, eframe::APP_KEY, &settings);
+    }
+
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        self.show_permalink_dialog(ctx);
         egui::CentralPanel::default().show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                ui.label("Session:");
+                ui.add(
+                    egui::TextEdit::singleline(&mut self.session_path)
+                        .desired_width(SESSION_PATH_WIDTH)
+                        .hint_text("session.json"),
+                );
+                let path = Path::new(&self.session_path).to_owned();
+                if ui.button("Open").clicked() {
+                    match Session::load(&path) {
+                        Ok(session) => {
+                            self.session_error = None;
+                            self.set_session(session);
+                        }
+                        Err(e) => self.session_error = Some(e),
+                    }
+                }
+                if ui.button("Save").clicked() {
+                    self.session_error = self.session().save(&path).err();
+                }
+                if ui.button("Copy permalink").clicked() {
+                    ui.ctx().copy_text(self.session().to_permalink());
+                }
+                if ui.button("Paste permalink").clicked() {
+                    self.permalink_str = Some(String::new());
+                    self.permalink_error = None;
+                }
+                if let Some(e) = &self.session_error {
+                    ui.colored_label(ui.visuals().error_fg_color, e);
+                }
+            });
             ui.columns(2, |uis| {
                 {
                     let ui = &mut uis[0];
-                    ui.label("Algorithm:");
-                    if ui.text_edit_singleline(&mut self.alg_str).changed() {
+                    ui.label("Setup:");
+                    let errors = Vec::from_iter(self.setup_error.clone());
+                    let r = ui.add(
+                        egui::TextEdit::singleline(&mut self.setup_str)
+                            .hint_text("(none)")
+                            .layouter(&mut |ui, text, wrap_width| {
+                                layout_with_errors(ui, text.as_str(), &errors, wrap_width)
+                            }),
+                    );
+                    if r.changed() {
                         self.recompute_everything();
                     }
-                    match !self.alg_is_valid {
-                        true => ui.colored_label(ui.visuals().error_fg_color, "error!"),
-                        false => ui.label(self.moves.to_string()),
+                    if let Some(e) = &self.setup_error {
+                        ui.colored_label(
+                            ui.visuals().error_fg_color,
+                            format!("column {}: {}", e.columns.start, e.message),
+                        );
+                    }
+                    ui.label("Algorithm:");
+                    let errors = Vec::from_iter(self.alg_error.clone());
+                    let r = ui.add(egui::TextEdit::singleline(&mut self.alg_str).layouter(
+                        &mut |ui, text, wrap_width| {
+                            layout_with_errors(ui, text.as_str(), &errors, wrap_width)
+                        },
+                    ));
+                    if r.changed() {
+                        match Session::from_alg_url(&self.alg_str) {
+                            Some(Ok(mut session)) => {
+                                self.session_error = None;
+                                if self.session().has_edited_relations() {
+                                    self.session_error = Some(format!(
+                                        "kept the edited relations instead of the {} preset from the URL",
+                                        session.preset.map_or("default", |p| p.name()),
+                                    ));
+                                    session.relations = self.relations_str.clone();
+                                    session.preset = self.preset;
+                                }
+                                self.set_session(session);
+                            }
+                            _ => self.recompute_everything(),
+                        }
+                    }
+                    match &self.alg_error {
+                        Some(e) => ui.colored_label(
+                            ui.visuals().error_fg_color,
+                            format!("column {}: {}", e.columns.start, e.message),
+                        ),
+                        None => ui.label(self.analysis.moves.to_string()),
                     };
                 }
                 {
                     let ui = &mut uis[1];
-                    ui.label("Relations:");
-                    if ui.text_edit_multiline(&mut self.relations_str).changed() {
-                        self.recompute_everything();
+                    ui.horizontal(|ui| {
+                        ui.label("Relations:");
+                        egui::ComboBox::from_id_salt("preset")
+                            .selected_text(self.preset.map_or("Custom", |p| p.name()))
+                            .show_ui(ui, |ui| {
+                                for p in Preset::ALL {
+                                    let r =
+                                        ui.selectable_value(&mut self.preset, Some(p), p.name());
+                                    if r.clicked() {
+                                        self.relations_str = p.relations();
+                                        self.recompute_everything();
+                                    }
+                                }
+                            });
+                        let r = ui
+                            .add(
+                                egui::TextEdit::singleline(&mut self.kpuzzle_str)
+                                    .desired_width(80.0)
+                                    .hint_text("KPuzzle"),
+                            )
+                            .on_hover_text("3x3x3, 2x2x2, or a KPuzzle JSON file");
+                        if r.changed() && self.verify_kpuzzle.take().is_some() {
+                            self.recompute_everything();
+                        }
+                        if ui.button("Derive").clicked() {
+                            match load_kpuzzle(&self.kpuzzle_str).and_then(|k| derive_relations(&k))
+                            {
+                                Ok(relations) => {
+                                    self.kpuzzle_error = None;
+                                    self.preset = None;
+                                    self.relations_str = relations;
+                                    self.recompute_everything();
+                                }
+                                Err(e) => self.kpuzzle_error = Some(e),
+                            }
+                        }
+                        let mut verify = self.verify_kpuzzle.is_some();
+                        let r = ui
+                            .checkbox(&mut verify, "Verify")
+                            .on_hover_text("Check the analysis by simulating it on the KPuzzle");
+                        if r.changed() {
+                            self.verify_kpuzzle = None;
+                            if verify {
+                                match load_kpuzzle(&self.kpuzzle_str) {
+                                    Ok(k) => {
+                                        self.kpuzzle_error = None;
+                                        self.verify_kpuzzle = Some(k);
+                                    }
+                                    Err(e) => self.kpuzzle_error = Some(e),
+                                }
+                            }
+                            self.recompute_everything();
+                        }
+                    });
+                    if let Some(e) = &self.kpuzzle_error {
+                        ui.colored_label(ui.visuals().error_fg_color, e);
                     }
+                    egui::ScrollArea::vertical()
+                        .id_salt("relations")
+                        .max_height(RELATIONS_MAX_HEIGHT)
+                        .show(ui, |ui| {
+                            let errors = &self.relations_errors;
+                            let r = ui.add(
+                                egui::TextEdit::multiline(&mut self.relations_str)
+                                    .desired_width(f32::INFINITY)
+                                    .layouter(&mut |ui, text, wrap_width| {
+                                        layout_with_errors(ui, text.as_str(), errors, wrap_width)
+                                    }),
+                            );
+                            if r.changed() {
+                                self.recompute_everything();
+                            }
+                        });
                     match &self.relations_str_error {
                         Some(e) => ui.colored_label(ui.visuals().error_fg_color, e),
                         None => ui.label(""),
                     };
+                    if !self.relations_errors.is_empty() {
+                        egui::ScrollArea::vertical()
+                            .id_salt("relations_errors")
+                            .max_height(REPORT_MAX_HEIGHT)
+                            .show(ui, |ui| {
+                                for e in &self.relations_errors {
+                                    ui.colored_label(ui.visuals().error_fg_color, e.to_string());
+                                }
+                            });
+                    }
+                    if !self.generated_relations.is_empty() {
+                        egui::CollapsingHeader::new(format!(
+                            "Symmetries generated {} relations",
+                            self.generated_relations.len(),
+                        ))
+                        .id_salt("generated_relations")
+                        .show(ui, |ui| {
+                            egui::ScrollArea::vertical()
+                                .max_height(REPORT_MAX_HEIGHT)
+                                .show(ui, |ui| {
+                                    for r in &self.generated_relations {
+                                        ui.label(r.to_string());
+                                    }
+                                });
+                        });
+                    }
+                    if !self.consistency_issues.is_empty() {
+                        egui::ScrollArea::vertical()
+                            .id_salt("consistency_issues")
+                            .max_height(REPORT_MAX_HEIGHT)
+                            .show(ui, |ui| {
+                                for issue in &self.consistency_issues {
+                                    ui.colored_label(ui.visuals().warn_fg_color, issue.to_string());
+                                }
+                            });
+                    }
+                    ui.horizontal(|ui| {
+                        let r = ui
+                            .checkbox(&mut self.check_confluence, "Check confluence")
+                            .on_hover_text("List grips that two relations rewrite differently");
+                        if r.changed() {
+                            self.recompute_everything();
+                        }
+                        let r = ui
+                            .checkbox(&mut self.complete_relations, "Complete")
+                            .on_hover_text(
+                                "Add relations until no two relations rewrite a grip differently",
+                            );
+                        if r.changed() {
+                            self.recompute_everything();
+                        }
+                    });
+                    if let Some(pairs) = &self.critical_pairs {
+                        ui.label(format!(
+                            "{} critical pairs rewrite differently",
+                            pairs.len()
+                        ));
+                        egui::ScrollArea::vertical()
+                            .id_salt("critical_pairs")
+                            .max_height(REPORT_MAX_HEIGHT)
+                            .show(ui, |ui| {
+                                for pair in pairs {
+                                    ui.colored_label(ui.visuals().warn_fg_color, pair.to_string());
+                                }
+                            });
+                    }
+                    if let Some(added) = &self.added_relations {
+                        ui.label(format!("Added {} relations", added.len()));
+                        egui::ScrollArea::vertical()
+                            .id_salt("added_relations")
+                            .max_height(REPORT_MAX_HEIGHT)
+                            .show(ui, |ui| {
+                                for r in added {
+                                    ui.label(r.to_string());
+                                }
+                            });
+                    }
+                    match &self.verification {
+                        Some(Ok(v)) if v.is_ok() => {
+                            ui.label(v.to_string().trim_end());
+                        }
+                        Some(Ok(v)) => {
+                            egui::ScrollArea::vertical()
+                                .id_salt("verification")
+                                .max_height(REPORT_MAX_HEIGHT)
+                                .show(ui, |ui| {
+                                    ui.colored_label(
+                                        ui.visuals().error_fg_color,
+                                        v.to_string().trim_end(),
+                                    );
+                                });
+                        }
+                        Some(Err(e)) => {
+                            ui.colored_label(ui.visuals().error_fg_color, e);
+                        }
+                        None => (),
+                    }
                 }
             });
             ui.separator();
 
+            if let Some(last_step) = self.steps.len().checked_sub(1) {
+                ui.horizontal(|ui| {
+                    ui.label("Step:");
+                    ui.add(egui::Slider::new(&mut self.step, 0..=last_step));
+                    let mut job = egui::text::LayoutJob::default();
+                    for (i, m) in self.analysis.all_moves().enumerate() {
+                        let pre = if i == 0 { "" } else { " " };
+                        let color = match i.cmp(&self.step) {
+                            std::cmp::Ordering::Less => ui.visuals().text_color(),
+                            std::cmp::Ordering::Equal => ui.visuals().warn_fg_color,
+                            std::cmp::Ordering::Greater => ui.visuals().weak_text_color(),
+                        };
+                        job.append(&format!("{pre}{m}"), 0.0, text_format(color));
+                    }
+                    ui.label(job);
+                });
+            }
+
             let mut new_hovered_grip = None;
             let mut new_hovered_region = None;
 
             ui.style_mut().wrap_mode = Some(egui::TextWrapMode::Extend);
-            ui.columns(3, |uis| {
+            ui.columns(4, |uis| {
                 egui::ScrollArea::new([true; 2])
                     .auto_shrink(false)
                     .id_salt("regions")
                     .show(&mut uis[0], |ui| {
-                        ui.heading(format!("Grips ({})", self.grips.len()));
-                        for g in &self.grips {
+                        ui.heading(format!("Grips ({})", self.analysis.grips.len()));
+                        for g in &self.analysis.grips {
                             if self
                                 .display_grip(ui, g, false, ui.visuals().text_color())
                                 .contains_pointer()
@@ -281,8 +679,14 @@ impl eframe::App for App {
                     .auto_shrink(false)
                     .id_salt("grips")
                     .show(&mut uis[1], |ui| {
-                        ui.heading(format!("Regions ({})", self.regions.len()));
-                        for r in &self.regions {
+                        let regions = (self.analysis.regions.iter())
+                            .filter(|r| self.is_region_visible(r))
+                            .collect::<Vec<_>>();
+                        ui.heading(format!("Regions ({})", regions.len()));
+                        if let Some(e) = &self.region_pieces_error {
+                            ui.colored_label(ui.visuals().error_fg_color, e);
+                        }
+                        for r in regions {
                             if self
                                 .display_region(ui, r, &mut new_hovered_grip)
                                 .contains_pointer()
@@ -294,10 +698,69 @@ impl eframe::App for App {
 
                 egui::ScrollArea::new([true; 2])
                     .auto_shrink(false)
-                    .id_salt("results")
+                    .id_salt("step")
                     .show(&mut uis[2], |ui| {
-                        ui.heading(format!("Results ({})", self.results.len()));
-                        for (move_seq, regions_by_move_seq) in &self.results {
+                        let Some(step) = self.steps.get(self.step) else {
+                            return;
+                        };
+                        let next_move = self.analysis.all_moves().nth(self.step);
+                        ui.heading(format!(
+                            "After {} moves ({})",
+                            self.step,
+                            step.regions.len()
+                        ));
+                        for r in &step.regions {
+                            ui.horizontal(|ui| {
+                                ui.spacing_mut().item_spacing.x = 0.0;
+                                // Mark the regions that the next move splits
+                                match next_move {
+                                    Some(m) if step.split_by_next_move.contains(r) => {
+                                        ui.colored_label(ui.visuals().warn_fg_color, "✂ ")
+                                            .on_hover_text(format!("{m} splits this region"));
+                                    }
+                                    _ => {
+                                        ui.label("    ");
+                                    }
+                                }
+                                if self
+                                    .display_region(ui, r, &mut new_hovered_grip)
+                                    .contains_pointer()
+                                {
+                                    new_hovered_region = Some(r.clone());
+                                }
+                            });
+                        }
+                    });
+
+                egui::ScrollArea::new([true; 2])
+                    .auto_shrink(false)
+                    .id_salt("results")
+                    .show(&mut uis[3], |ui| {
+                        let results = (self.analysis.results.iter())
+                            .map(|(move_seq, regions_by_move_seq)| {
+                                let regions_by_move_seq = regions_by_move_seq
+                                    .iter()
+                                    .map(|(move_mask, regions)| {
+                                        let regions = (regions.iter())
+                                            .filter(|r| self.is_region_visible(r))
+                                            .collect::<Vec<_>>();
+                                        (move_mask, regions)
+                                    })
+                                    .filter(|(_, regions)| !regions.is_empty())
+                                    .collect::<Vec<_>>();
+                                (move_seq, regions_by_move_seq)
+                            })
+                            .filter(|(_, regions_by_move_seq)| !regions_by_move_seq.is_empty())
+                            .collect::<Vec<_>>();
+                        ui.horizontal(|ui| {
+                            ui.heading(format!("Results ({})", results.len()));
+                            if ui.button("Copy JSON").clicked() {
+                                let json = serde_json::to_string_pretty(&self.analysis)
+                                    .expect("analysis has no maps with non-string keys");
+                                ui.ctx().copy_text(json);
+                            }
+                        });
+                        for (move_seq, regions_by_move_seq) in results {
                             let move_seq_str = if move_seq.is_empty() {
                                 "(empty)".to_string()
                             } else {
@@ -308,13 +771,26 @@ impl eframe::App for App {
                                 let mut job = egui::text::LayoutJob::default();
                                 let mut is_first = true;
                                 job.append("    ", 0.0, text_format(ui.visuals().text_color()));
-                                for (m, include) in self.moves.iter().zip(move_mask) {
+                                let setup_len = self.analysis.setup.len();
+                                for (i, (m, include)) in
+                                    self.analysis.all_moves().zip(move_mask).enumerate()
+                                {
                                     let pre = if is_first { "" } else { " " };
                                     is_first = false;
-                                    let color = ui
-                                        .visuals()
-                                        .text_color()
-                                        .gamma_multiply(if *include { 1.25 } else { 0.5 });
+                                    if i == setup_len && setup_len > 0 {
+                                        let color = ui.visuals().weak_text_color();
+                                        job.append(" |", 0.0, text_format(color));
+                                    }
+                                    // Setup moves are shown in the hyperlink
+                                    // color to distinguish them from the
+                                    // algorithm
+                                    let color = if i < setup_len {
+                                        ui.visuals().hyperlink_color
+                                    } else {
+                                        ui.visuals().text_color()
+                                    };
+                                    let color =
+                                        color.gamma_multiply(if *include { 1.25 } else { 0.5 });
                                     job.append(&format!("{pre}{m}"), 0.0, text_format(color));
                                 }
                                 ui.label(job);
@@ -341,124 +817,42 @@ impl eframe::App for App {
     }
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
-pub struct Grip {
-    grip_name: String,
-    transform: MoveSeq,
-}
-impl fmt::Display for Grip {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let Self {
-            grip_name,
-            transform,
-        } = self;
-
-        if self.transform.is_empty() {
-            write!(f, "{grip_name}")
-        } else {
-            write!(f, "{grip_name} × ({transform})")
-        }
-    }
-}
-impl Grip {
-    pub fn new(grip_name: String) -> Self {
-        Self {
-            grip_name,
-            transform: MoveSeq::new(),
-        }
-    }
-    #[must_use]
-    pub fn do_move(mut self, m: Move, relations: &[Relation]) -> Self {
-        self.transform.push_back(m);
-
-        // Grip is not affected by its own move
-        if self.transform.len() == 1 {
-            self.transform.pop_front_if_fam(&self.grip_name);
-        }
-
-        // Apply relations
-        for r in relations {
-            if r.grip_to_replace.grip_name == self.grip_name
-                && self
-                    .transform
-                    .pop_front_if_matches(&r.grip_to_replace.transform)
-            {
-                self.grip_name = r.new_grip_name.clone();
-            }
-        }
-
-        self
-    }
+fn text_format(color: egui::Color32) -> egui::TextFormat {
+    egui::TextFormat::simple(egui::FontId::proportional(13.0), color)
 }
 
-#[derive(Debug, Default, Clone, PartialEq, Eq, PartialOrd, Ord)]
-pub struct Region {
-    include: BTreeSet<Grip>,
-    exclude: BTreeSet<Grip>,
-}
-impl fmt::Display for Region {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let s = itertools::chain(
-            self.include.iter().map(|s| format!("{s}")),
-            self.exclude.iter().map(|s| format!("!{s}")),
-        )
-        .join(", ");
-
-        write!(f, "{{{s}}}")
-    }
-}
-impl Region {
-    pub fn has_grip(&self, grip: Grip) -> Option<bool> {
-        if self.include.contains(&grip) {
-            Some(true)
-        } else if self.exclude.contains(&grip) {
-            Some(false)
-        } else {
-            None
-        }
-    }
-    /// returns `[not_affected, affected]`
-    #[must_use]
-    pub fn do_move(self, m: Move, relations: &[Relation]) -> [Option<Region>; 2] {
-        match self.has_grip(Grip::new(m.quantum.family.clone())) {
-            Some(false) => [Some(self), None],
-            Some(true) => [None, Some(self.do_move_unchecked(m, relations))],
-            None => {
-                let mut excluded = self.clone();
-                excluded.exclude.insert(Grip::new(m.quantum.family.clone()));
-                let mut included = self.do_move_unchecked(m.clone(), relations);
-                included.include.insert(Grip::new(m.quantum.family.clone()));
-                [Some(excluded), Some(included)]
-            }
+/// Lays out the text of a text editor with errors underlined.
+fn layout_with_errors(
+    ui: &egui::Ui,
+    text: &str,
+    errors: &[ParseError],
+    wrap_width: f32,
+) -> Arc<egui::Galley> {
+    let format = egui::TextFormat::simple(
+        egui::FontSelection::default().resolve(ui.style()),
+        ui.visuals().widgets.inactive.text_color(),
+    );
+    let error_format = egui::TextFormat {
+        underline: egui::Stroke::new(ERROR_UNDERLINE_WIDTH, ui.visuals().error_fg_color),
+        ..format.clone()
+    };
+
+    let mut ranges = errors
+        .iter()
+        .filter_map(|e| e.byte_range(text))
+        .collect::<Vec<_>>();
+    ranges.sort_by_key(|r| r.start);
+    let mut job = egui::text::LayoutJob::default();
+    let mut i = 0;
+    for r in ranges {
+        let start = r.start.max(i);
+        if start < r.end {
+            job.append(&text[i..start], 0.0, format.clone());
+            job.append(&text[start..r.end], 0.0, error_format.clone());
+            i = r.end;
         }
     }
-    #[must_use]
-    fn do_move_unchecked(mut self, m: Move, relations: &[Relation]) -> Self {
-        for set in [&mut self.include, &mut self.exclude] {
-            *set = std::mem::take(set)
-                .into_iter()
-                .map(|g| g.do_move(m.clone(), relations))
-                .collect();
-        }
-
-        self
-    }
-}
-
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
-pub struct Relation {
-    pub new_grip_name: String,
-    pub grip_to_replace: Grip,
-}
-
-fn validate_grip_name(s: &str) -> Result<(), String> {
-    if s.chars().all(|c| c.is_alphabetic() || c == '_') {
-        Ok(())
-    } else {
-        Err(format!("invalid grip {s:?}"))
-    }
-}
-
-fn text_format(color: egui::Color32) -> egui::TextFormat {
-    egui::TextFormat::simple(egui::FontId::proportional(13.0), color)
+    job.append(&text[i..], 0.0, format);
+    job.wrap.max_width = wrap_width;
+    ui.fonts_mut(|f| f.layout_job(job))
 }
@@ -1,6 +1,9 @@
 use std::{collections::VecDeque, fmt};
 
 use cubing::alg::{Alg, AlgNode, Move};
+use serde::{Serialize, Serializer};
+
+use crate::Puzzle;
 
 #[derive(Debug, Default, Clone, PartialEq, Eq, Hash)]
 pub struct MoveSeq(VecDeque<Move>);
@@ -20,6 +23,12 @@ impl fmt::Display for MoveSeq {
     }
 }
 
+impl Serialize for MoveSeq {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
 impl PartialOrd for MoveSeq {
     fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
         Some(self.cmp(other))
@@ -34,45 +43,107 @@ impl Ord for MoveSeq {
 
 impl MoveSeq {
     fn cmp_key(&self) -> impl Iterator<Item = impl Ord> {
-        self.0.iter().map(|m| {
-            (
-                match &m.quantum.prefix {
-                    Some(cubing::alg::MovePrefix::Layer(move_layer)) => {
-                        [None, Some(move_layer.layer)]
-                    }
-                    Some(cubing::alg::MovePrefix::Range(move_range)) => {
-                        [Some(move_range.inner_layer), Some(move_range.outer_layer)]
-                    }
-                    None => [None; 2],
-                },
-                &m.quantum.family,
-                m.amount,
-            )
-        })
+        self.0.iter().map(|m| (quantum_key(m), m.amount))
     }
 
     pub fn new() -> Self {
         Self::default()
     }
-    pub fn from_alg(alg: &Alg) -> Self {
+    /// Returns the moves of an algorithm in canonical form, with grouping,
+    /// commutators, and conjugates expanded.
+    pub fn from_alg(alg: &Alg, puzzle: &Puzzle) -> Self {
         let mut ret = Self::new();
-        ret.extend_from_alg(alg);
+        ret.extend_from_alg(alg, puzzle, Self::push_back);
         ret
     }
-    pub fn push_back(&mut self, m: Move) {
-        if let Some(last) = self.0.iter_mut().last()
-            && last.quantum.family == m.quantum.family
-        {
-            last.amount += m.amount;
-            if last.amount == 0 {
-                self.0.pop_back();
+    /// Returns the moves of an algorithm in the order they are written, with
+    /// grouping, commutators, and conjugates expanded and adjacent moves on the
+    /// same layers merged.
+    pub fn from_alg_in_order(alg: &Alg, puzzle: &Puzzle) -> Self {
+        let mut ret = Self::new();
+        ret.extend_from_alg(alg, puzzle, Self::push_back_in_order);
+        ret
+    }
+    /// Appends a move and keeps the sequence in canonical form.
+    ///
+    /// The move is merged with an earlier move on the same layers if every move
+    /// in between commutes with it, and its amount is reduced modulo the grip's
+    /// order. The moves are then reordered so that sequences that are equal up
+    /// to commutation are equal.
+    pub fn push_back(&mut self, mut m: Move, puzzle: &Puzzle) {
+        let mut i = self.0.len();
+        while i > 0 {
+            let prev = &self.0[i - 1];
+            if prev.quantum == m.quantum {
+                // Merge with `prev` and then re-append the moves after it,
+                // which may now cancel with the moves before it.
+                let tail = self.0.split_off(i);
+                m.amount += self.0.pop_back().expect("prev exists").amount;
+                m.amount = puzzle.reduce_amount(&m.quantum.family, m.amount);
+                if m.amount != 0 {
+                    self.0.push_back(m);
+                }
+                self.sort_commuting(puzzle);
+                for t in tail {
+                    self.push_back(t, puzzle);
+                }
+                return;
+            }
+            if !puzzle.commute(&prev.quantum.family, &m.quantum.family) {
+                break;
             }
+            i -= 1;
+        }
+
+        m.amount = puzzle.reduce_amount(&m.quantum.family, m.amount);
+        if m.amount == 0 {
             return;
         }
         self.0.push_back(m);
+        self.sort_commuting(puzzle);
     }
-    pub fn pop_front_if_fam(&mut self, family: &str) {
-        if self.first().is_some_and(|m| m.quantum.family == family) {
+    /// Appends a move, merging it only with the last move if it is on the same
+    /// layers, so that the sequence stays in the order it was written.
+    pub fn push_back_in_order(&mut self, mut m: Move, puzzle: &Puzzle) {
+        if let Some(last) = self.0.back()
+            && last.quantum == m.quantum
+        {
+            m.amount += self.0.pop_back().expect("last exists").amount;
+        }
+        m.amount = puzzle.reduce_amount(&m.quantum.family, m.amount);
+        if m.amount != 0 {
+            self.0.push_back(m);
+        }
+    }
+    /// Reorders the moves into the lexicographic normal form of their trace:
+    /// each move is the smallest of the remaining moves that commute with
+    /// every remaining move before them. This is the same for all sequences
+    /// that are equal up to commutation, even when commutation is not
+    /// transitive.
+    fn sort_commuting(&mut self, puzzle: &Puzzle) {
+        let mut rest = std::mem::take(&mut self.0);
+        while !rest.is_empty() {
+            let next = (0..rest.len())
+                .filter(|&i| {
+                    let family = &rest[i].quantum.family;
+                    (rest.iter().take(i)).all(|m| puzzle.commute(&m.quantum.family, family))
+                })
+                .min_by_key(|&i| (quantum_key(&rest[i]), i))
+                .expect("the first move is always available");
+            self.0
+                .push_back(rest.remove(next).expect("index is in range"));
+        }
+    }
+    /// Returns the inverse move sequence.
+    pub fn inverse(&self, puzzle: &Puzzle) -> Self {
+        let mut ret = Self::new();
+        for m in self.0.iter().rev() {
+            ret.push_back(m.invert(), puzzle);
+        }
+        ret
+    }
+    pub fn pop_front_while(&mut self, mut f: impl FnMut(&Move) -> bool) {
+        while self.first().is_some_and(&mut f) {
             self.0.pop_front();
         }
     }
@@ -92,38 +163,38 @@ impl MoveSeq {
     pub fn first(&self) -> Option<&Move> {
         self.0.iter().next()
     }
-    fn extend_from_alg(&mut self, alg: &Alg) {
+    fn extend_from_alg(&mut self, alg: &Alg, puzzle: &Puzzle, push: fn(&mut Self, Move, &Puzzle)) {
         for node in &alg.nodes {
             match node {
-                AlgNode::MoveNode(m) => self.push_back(m.clone()),
+                AlgNode::MoveNode(m) => push(self, m.clone(), puzzle),
                 AlgNode::GroupingNode(grouping) if grouping.amount.is_positive() => {
                     for _ in 0..grouping.amount {
-                        self.extend_from_alg(&grouping.alg);
+                        self.extend_from_alg(&grouping.alg, puzzle, push);
                     }
                 }
                 AlgNode::GroupingNode(grouping) if grouping.amount.is_negative() => {
                     let a = grouping.alg.invert();
                     for _ in 0..grouping.amount.abs() {
-                        self.extend_from_alg(&a);
+                        self.extend_from_alg(&a, puzzle, push);
                     }
                 }
                 AlgNode::CommutatorNode(commutator) => {
-                    self.extend_from_alg(&commutator.a);
-                    self.extend_from_alg(&commutator.b);
-                    self.extend_from_alg(&commutator.a.invert());
-                    self.extend_from_alg(&commutator.b.invert());
+                    self.extend_from_alg(&commutator.a, puzzle, push);
+                    self.extend_from_alg(&commutator.b, puzzle, push);
+                    self.extend_from_alg(&commutator.a.invert(), puzzle, push);
+                    self.extend_from_alg(&commutator.b.invert(), puzzle, push);
                 }
                 AlgNode::ConjugateNode(conjugate) => {
-                    self.extend_from_alg(&conjugate.a);
-                    self.extend_from_alg(&conjugate.b);
-                    self.extend_from_alg(&conjugate.a.invert());
+                    self.extend_from_alg(&conjugate.a, puzzle, push);
+                    self.extend_from_alg(&conjugate.b, puzzle, push);
+                    self.extend_from_alg(&conjugate.a.invert(), puzzle, push);
                 }
                 _ => (),
             }
         }
     }
 
-    pub fn iter(&self) -> impl Iterator<Item = &Move> {
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = &Move> {
         self.0.iter()
     }
 
@@ -135,3 +206,67 @@ impl MoveSeq {
         self.0.is_empty()
     }
 }
+
+fn quantum_key(m: &Move) -> impl Ord + use<'_> {
+    (
+        match &m.quantum.prefix {
+            Some(cubing::alg::MovePrefix::Layer(move_layer)) => [None, Some(move_layer.layer)],
+            Some(cubing::alg::MovePrefix::Range(move_range)) => {
+                [Some(move_range.inner_layer), Some(move_range.outer_layer)]
+            }
+            None => [None; 2],
+        },
+        &m.quantum.family,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn puzzle() -> Puzzle {
+        "R: 4\nL: 4\nU: 4\nR | L".parse().unwrap()
+    }
+    fn moves(s: &str) -> MoveSeq {
+        MoveSeq::from_alg(&s.parse().unwrap(), &puzzle())
+    }
+
+    #[test]
+    fn merge_across_commuting_moves() {
+        assert_eq!(moves("R L R'").to_string(), "L");
+        assert_eq!(moves("R L R"), moves("L R2"));
+    }
+
+    #[test]
+    fn commuting_moves_are_sorted() {
+        assert_eq!(moves("L R"), moves("R L"));
+        assert_ne!(moves("R U"), moves("U R"));
+    }
+
+    #[test]
+    fn amounts_are_reduced() {
+        assert!(moves("U2 U2").is_empty());
+        assert_eq!(moves("U3").to_string(), "U'");
+        assert_eq!(moves("U2'").to_string(), "U2");
+    }
+
+    #[test]
+    fn non_transitive_commutation() {
+        // A and C commute with B but not with each other, as non-adjacent
+        // faces of a megaminx do
+        let puzzle: Puzzle = "A | B\nB | C".parse().unwrap();
+        let moves = |s: &str| MoveSeq::from_alg(&s.parse().unwrap(), &puzzle);
+        assert_eq!(moves("A B C"), moves("B A C"));
+        assert_eq!(moves("A C B"), moves("B A C"));
+        assert_ne!(moves("A C"), moves("C A"));
+        assert_eq!(moves("C B A"), moves("B C A"));
+        assert_eq!(moves("C A B").to_string(), "B C A");
+    }
+
+    #[test]
+    fn cancellation_enables_earlier_merge() {
+        assert_eq!(moves("R U U' R'").to_string(), "");
+        assert_eq!(moves("R U L U' R").to_string(), "R U L U' R");
+        assert_eq!(moves("R L U U' R'").to_string(), "L");
+    }
+}
@@ -0,0 +1,108 @@
+use std::{fmt, ops::Range, str::FromStr};
+
+use cubing::alg::{Alg, Move};
+
+/// Error at a location in a text, such as the relations or an algorithm.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    /// Line of the error, starting at 1.
+    pub line: usize,
+    /// Columns of the error in characters, starting at 1.
+    pub columns: Range<usize>,
+    pub message: String,
+}
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Self {
+            line,
+            columns,
+            message,
+        } = self;
+        write!(f, "line {line}, column {}: {message}", columns.start)
+    }
+}
+impl ParseError {
+    /// Returns the byte range of the error in `text`, if it is in `text`.
+    pub fn byte_range(&self, text: &str) -> Option<Range<usize>> {
+        let line = text.split('\n').nth(self.line.checked_sub(1)?)?;
+        let line_start = line.as_ptr() as usize - text.as_ptr() as usize;
+        let byte_index = |column: usize| {
+            line.char_indices()
+                .map(|(i, _)| i)
+                .nth(column.saturating_sub(1))
+                .unwrap_or(line.len())
+        };
+        let range = byte_index(self.columns.start)..byte_index(self.columns.end);
+        (!range.is_empty()).then(|| line_start + range.start..line_start + range.end)
+    }
+}
+
+/// Parses an algorithm on a single line, locating the error if it is invalid.
+///
+/// `cubing` does not report where an algorithm is invalid and prints every
+/// failed parse to stderr, so each move and bracket is checked first, which is
+/// silent. Only errors that this misses are reported for the whole algorithm.
+pub fn parse_alg(s: &str) -> Result<Alg, ParseError> {
+    let error = |range: Range<usize>, message: String| {
+        let column = |i: usize| s[..i].chars().count() + 1;
+        ParseError {
+            line: 1,
+            columns: column(range.start)..column(range.end).max(column(range.start) + 1),
+            message,
+        }
+    };
+
+    // Everything after `//` is a comment
+    let code = s.split("//").next().unwrap_or_default();
+    // Each open bracket, with whether it has a `,` or `:` yet
+    let mut open_brackets: Vec<(usize, char, bool)> = vec![];
+    let mut word_start = None;
+    let mut is_amount = false;
+    for (i, c) in code.char_indices().chain([(code.len(), ' ')]) {
+        if !c.is_whitespace() && !"[]():,".contains(c) {
+            word_start.get_or_insert(i);
+            continue;
+        }
+        if let Some(start) = word_start.take() {
+            let word = &code[start..i];
+            // A number after `)` is the amount of a grouping, and `.` is a
+            // pause
+            let is_valid = if is_amount {
+                word.trim_end_matches('\'')
+                    .chars()
+                    .all(|c| c.is_ascii_digit())
+            } else {
+                word.chars().all(|c| c == '.') || Move::from_str(word).is_ok()
+            };
+            if !is_valid {
+                return Err(error(start..i, format!("invalid move {word:?}")));
+            }
+        }
+        is_amount = c == ')' || c == ']';
+        match c {
+            '[' | '(' => open_brackets.push((i, c, false)),
+            ']' | ')' => match open_brackets.pop() {
+                Some((_, '(', _)) if c == ')' => (),
+                Some((_, '[', true)) if c == ']' => (),
+                Some((start, '[', false)) if c == ']' => {
+                    let message = "missing ',' or ':' in '[...]'".to_string();
+                    return Err(error(start..i + 1, message));
+                }
+                _ => return Err(error(i..i + 1, format!("unmatched '{c}'"))),
+            },
+            ',' | ':' => match open_brackets.last_mut() {
+                Some((_, '[', has_separator @ false)) => *has_separator = true,
+                Some((_, '[', true)) => {
+                    return Err(error(i..i + 1, format!("extra '{c}' in '[...]'")));
+                }
+                _ => return Err(error(i..i + 1, format!("'{c}' outside of '[...]'"))),
+            },
+            _ => (),
+        }
+    }
+    if let Some((i, c, _)) = open_brackets.pop() {
+        return Err(error(i..i + 1, format!("unclosed '{c}'")));
+    }
+
+    Alg::from_str(s).map_err(|e| error(0..s.len(), e.to_string()))
+}
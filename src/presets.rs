@@ -0,0 +1,506 @@
+use std::{collections::BTreeSet, f64::consts::TAU, fmt, str::FromStr};
+
+use cubing::kpuzzle::KPuzzle;
+use itertools::Itertools;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/// Built-in puzzle with generated relations.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Preset {
+    Cube3x3x3,
+    Megaminx,
+    Pyraminx,
+    Skewb,
+    Fto,
+    Helicopter,
+    Cube3x3x3x3,
+}
+impl fmt::Display for Preset {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+impl Serialize for Preset {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.name())
+    }
+}
+impl<'de> Deserialize<'de> for Preset {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(serde::de::Error::custom)
+    }
+}
+impl FromStr for Preset {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|p| p.name().eq_ignore_ascii_case(s))
+            .ok_or_else(|| {
+                let names = Self::ALL.iter().map(|p| p.name()).join(", ");
+                format!("unknown puzzle preset {s:?}; expected one of {names}")
+            })
+    }
+}
+impl Preset {
+    pub const ALL: [Self; 7] = [
+        Self::Cube3x3x3,
+        Self::Megaminx,
+        Self::Pyraminx,
+        Self::Skewb,
+        Self::Fto,
+        Self::Helicopter,
+        Self::Cube3x3x3x3,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Cube3x3x3 => "3x3x3",
+            Self::Megaminx => "Megaminx",
+            Self::Pyraminx => "Pyraminx",
+            Self::Skewb => "Skewb",
+            Self::Fto => "FTO",
+            Self::Helicopter => "Helicopter",
+            Self::Cube3x3x3x3 => "3^4",
+        }
+    }
+
+    /// Returns the complete relations for the puzzle, including grip orders
+    /// and commutations.
+    pub fn relations(self) -> String {
+        self.geometry().relations()
+    }
+
+    /// Returns a KPuzzle for the puzzle, if `cubing` has one.
+    pub fn kpuzzle(self) -> Option<KPuzzle> {
+        match self {
+            Self::Cube3x3x3 => Some(cubing::puzzles::cube3x3x3_kpuzzle().clone()),
+            _ => None,
+        }
+    }
+
+    fn geometry(self) -> Geometry {
+        match self {
+            Self::Cube3x3x3 => {
+                let mut g = Geometry::default();
+                for (name, dir) in CUBE_FACES {
+                    g.grip(name, dir, 4);
+                }
+                g.slice("M", [-1.0, 0.0, 0.0, 0.0], 4);
+                g.slice("E", [0.0, -1.0, 0.0, 0.0], 4);
+                g.slice("S", [0.0, 0.0, 1.0, 0.0], 4);
+                g.rotation("x", [1.0, 0.0, 0.0, 0.0], 4);
+                g.rotation("y", [0.0, 1.0, 0.0, 0.0], 4);
+                g.rotation("z", [0.0, 0.0, 1.0, 0.0], 4);
+                g
+            }
+            Self::Megaminx => {
+                // Angle between adjacent face normals of a dodecahedron
+                let (sin, cos) = (2.0_f64.atan().sin(), 2.0_f64.atan().cos());
+                let mut g = Geometry {
+                    // Faces that are not adjacent share no pieces
+                    max_disjoint_dot: Some(0.0),
+                    ..Default::default()
+                };
+                g.grip("U", [0.0, 1.0, 0.0, 0.0], 5);
+                g.grip("D", [0.0, -1.0, 0.0, 0.0], 5);
+                // Faces around U, clockwise from the front, and their opposites
+                let ring = [
+                    ("F", "B"),
+                    ("L", "DR"),
+                    ("BL", "FR"),
+                    ("BR", "FL"),
+                    ("R", "DL"),
+                ];
+                for (i, (upper, lower)) in ring.into_iter().enumerate() {
+                    let azimuth = i as f64 * TAU / 5.0;
+                    let dir = [-azimuth.sin() * sin, cos, azimuth.cos() * sin, 0.0];
+                    g.grip(upper, dir, 5);
+                    g.grip(lower, neg(dir), 5);
+                }
+                g
+            }
+            Self::Pyraminx => {
+                let (a, b) = ((2.0_f64 / 3.0).sqrt(), 2.0_f64.sqrt() / 3.0);
+                let mut g = Geometry::default();
+                g.grip("U", [0.0, 1.0, 0.0, 0.0], 3);
+                g.grip("L", [-a, -1.0 / 3.0, b, 0.0], 3);
+                g.grip("R", [a, -1.0 / 3.0, b, 0.0], 3);
+                g.grip("B", [0.0, -1.0 / 3.0, -2.0 * b, 0.0], 3);
+                for (tip, parent) in [("u", "U"), ("l", "L"), ("r", "R"), ("b", "B")] {
+                    g.tip(tip, parent, 3);
+                }
+                g
+            }
+            Self::Skewb => {
+                // WCA notation names four corners; the rest are named after
+                // the faces they touch.
+                let mut g = Geometry::default();
+                g.grip("R", [1.0, -1.0, -1.0, 0.0], 3);
+                g.grip("U", [-1.0, 1.0, -1.0, 0.0], 3);
+                g.grip("L", [-1.0, -1.0, 1.0, 0.0], 3);
+                g.grip("B", [-1.0, -1.0, -1.0, 0.0], 3);
+                g.grip("UFR", [1.0, 1.0, 1.0, 0.0], 3);
+                g.grip("UFL", [-1.0, 1.0, 1.0, 0.0], 3);
+                g.grip("UBR", [1.0, 1.0, -1.0, 0.0], 3);
+                g.grip("DFR", [1.0, -1.0, 1.0, 0.0], 3);
+                g
+            }
+            Self::Fto => {
+                // Held with a vertex facing front and U on top
+                let r = 2.0_f64.sqrt();
+                let mut g = Geometry::default();
+                for (name, opposite, dir) in [
+                    ("U", "D", [0.0, r, 1.0, 0.0]),
+                    ("F", "B", [0.0, -r, 1.0, 0.0]),
+                    ("R", "BL", [r, 0.0, 1.0, 0.0]),
+                    ("L", "BR", [-r, 0.0, 1.0, 0.0]),
+                ] {
+                    g.grip(name, dir, 3);
+                    g.grip(opposite, neg(dir), 3);
+                }
+                g
+            }
+            Self::Helicopter => {
+                // Each grip is the half of the cube nearest an edge, and
+                // only half turns keep the pieces aligned
+                let face_dir = |face: char| {
+                    let (_, dir) = CUBE_FACES
+                        .iter()
+                        .find(|(f, _)| f.starts_with(face))
+                        .expect("face exists");
+                    *dir
+                };
+                // Edges that do not share a corner share no pieces
+                let mut g = Geometry {
+                    max_disjoint_dot: Some(0.0),
+                    ..Default::default()
+                };
+                for name in [
+                    "UF", "UR", "UB", "UL", "DF", "DR", "DB", "DL", "FR", "FL", "BR", "BL",
+                ] {
+                    let [a, b] = [0, 1].map(|i| face_dir(name.as_bytes()[i] as char));
+                    g.grip(name, std::array::from_fn(|i| a[i] + b[i]), 2);
+                }
+                g
+            }
+            Self::Cube3x3x3x3 => {
+                // RKT view: the six outer cells twist like faces of a 3x3x3,
+                // which fixes the inner and outer cells.
+                let mut g = Geometry::default();
+                for (name, dir) in CUBE_FACES {
+                    g.grip(name, dir, 4);
+                }
+                g.fixed_grip("I", [0.0, 0.0, 0.0, -1.0]);
+                g.fixed_grip("O", [0.0, 0.0, 0.0, 1.0]);
+                g
+            }
+        }
+    }
+}
+
+type Vector = [f64; 4];
+
+const CUBE_FACES: [(&str, Vector); 6] = [
+    ("U", [0.0, 1.0, 0.0, 0.0]),
+    ("D", [0.0, -1.0, 0.0, 0.0]),
+    ("F", [0.0, 0.0, 1.0, 0.0]),
+    ("B", [0.0, 0.0, -1.0, 0.0]),
+    ("R", [1.0, 0.0, 0.0, 0.0]),
+    ("L", [-1.0, 0.0, 0.0, 0.0]),
+];
+
+/// Grips and twists of a puzzle, from which relations are generated.
+///
+/// Each grip has a direction. A clockwise twist of order `n` around a
+/// direction rotates every other grip by `1/n` of a turn, and the grip whose
+/// direction matches the result is the grip it becomes. Grips with parallel
+/// directions are disjoint, and so are grips whose directions are far enough
+/// apart if [`Geometry::max_disjoint_dot`] is set.
+#[derive(Debug, Default)]
+struct Geometry {
+    grips: Vec<GripDef>,
+    twists: Vec<Twist>,
+    /// Largest dot product of the directions of two grips on different axes
+    /// that have no pieces in common, if there are any such grips.
+    max_disjoint_dot: Option<f64>,
+}
+#[derive(Debug)]
+struct GripDef {
+    name: &'static str,
+    dir: Vector,
+    /// Whether the grip is a slice, which matches grips in either direction
+    /// along its axis.
+    slice: bool,
+    /// Grip that contains this grip, if this grip is a tip. A tip has the
+    /// same direction as its parent but only contains the pieces closest to
+    /// it, which no other grip moves.
+    parent: Option<&'static str>,
+}
+#[derive(Debug)]
+struct Twist {
+    family: &'static str,
+    dir: Vector,
+    order: i32,
+    /// Whether the twist rotates the whole puzzle.
+    is_rotation: bool,
+}
+impl Geometry {
+    fn grip(&mut self, name: &'static str, dir: Vector, order: i32) {
+        self.add_grip(name, dir, false);
+        self.twist(name, dir, order, false);
+    }
+    fn slice(&mut self, name: &'static str, dir: Vector, order: i32) {
+        self.add_grip(name, dir, true);
+        self.twist(name, dir, order, false);
+    }
+    /// Adds a tip of the grip `parent`, such as `u` for `U` on a pyraminx.
+    fn tip(&mut self, name: &'static str, parent: &'static str, order: i32) {
+        let dir = self
+            .grips
+            .iter()
+            .find(|g| g.name == parent)
+            .expect("parent grip exists")
+            .dir;
+        self.grips.push(GripDef {
+            name,
+            dir,
+            slice: false,
+            parent: Some(parent),
+        });
+        self.twist(name, dir, order, false);
+    }
+    fn fixed_grip(&mut self, name: &'static str, dir: Vector) {
+        self.add_grip(name, dir, false);
+    }
+    fn rotation(&mut self, family: &'static str, dir: Vector, order: i32) {
+        self.twist(family, dir, order, true);
+    }
+    fn twist(&mut self, family: &'static str, dir: Vector, order: i32, is_rotation: bool) {
+        let dir = normalize(dir);
+        self.twists.push(Twist {
+            family,
+            dir,
+            order,
+            is_rotation,
+        });
+    }
+    fn add_grip(&mut self, name: &'static str, dir: Vector, slice: bool) {
+        let dir = normalize(dir);
+        self.grips.push(GripDef {
+            name,
+            dir,
+            slice,
+            parent: None,
+        });
+    }
+
+    /// Returns whether grips on different axes with directions `a` and `b` have
+    /// no pieces in common.
+    fn is_disjoint(&self, a: Vector, b: Vector) -> bool {
+        !parallel(a, b)
+            && self
+                .max_disjoint_dot
+                .is_some_and(|max| dot(a, b) < max + 1e-6)
+    }
+
+    /// Returns whether `twist` moves none of the pieces of grip `g`. A tip has
+    /// pieces in common only with the grips on its axis.
+    fn misses(&self, g: &GripDef, twist: &Twist) -> bool {
+        let is_tip = |name| {
+            self.grips
+                .iter()
+                .any(|g| g.name == name && g.parent.is_some())
+        };
+        !twist.is_rotation
+            && !parallel(g.dir, twist.dir)
+            && (self.is_disjoint(g.dir, twist.dir) || g.parent.is_some() || is_tip(twist.family))
+    }
+
+    /// Returns whether `g` is a grip that cannot be twisted, such as `I` on the
+    /// 3^4, and `twist` maps it to itself.
+    fn fixes(&self, g: &GripDef, twist: &Twist) -> bool {
+        let angle = TAU / twist.order as f64;
+        !twist.is_rotation
+            && self.twists.iter().all(|t| t.family != g.name)
+            && approx_eq(rotate(g.dir, twist.dir, angle), g.dir)
+    }
+
+    /// Returns the grip of the same kind as `like` with direction `dir`.
+    fn find_grip(&self, dir: Vector, like: &GripDef) -> Option<&GripDef> {
+        let slice = like.slice;
+        self.grips.iter().find(|g| {
+            g.slice == slice
+                && g.parent.is_some() == like.parent.is_some()
+                && (approx_eq(g.dir, dir) || (slice && approx_eq(g.dir, neg(dir))))
+        })
+    }
+
+    fn relations(&self) -> String {
+        let mut out = String::new();
+
+        for t in &self.twists {
+            out += &format!("{}: {}\n", t.family, t.order);
+        }
+        let rotations = self.twists.iter().filter(|t| t.is_rotation).collect_vec();
+        if !rotations.is_empty() {
+            out += &format!(
+                "rotation {}\n",
+                rotations.iter().map(|t| t.family).join(" ")
+            );
+        }
+        out += "\n";
+
+        // Twists around the same axis commute
+        let mut axes: Vec<(Vector, Vec<&str>)> = vec![];
+        for t in &self.twists {
+            match axes.iter_mut().find(|(dir, _)| parallel(*dir, t.dir)) {
+                Some((_, families)) => families.push(t.family),
+                None => axes.push((t.dir, vec![t.family])),
+            }
+        }
+        for (_, families) in &axes {
+            if families.len() > 1 {
+                out += &format!("{}\n", families.join(" | "));
+            }
+        }
+        // So do twists of grips with no pieces in common
+        let twists = self.twists.iter().filter(|t| !t.is_rotation);
+        for (a, b) in twists.tuple_combinations() {
+            let b_grip = self.grips.iter().find(|g| g.name == b.family);
+            if b_grip.is_some_and(|g| self.misses(g, a)) {
+                out += &format!("{} | {}\n", a.family, b.family);
+            }
+        }
+        // And twists with grips that they fix and that cannot be twisted
+        for t in &self.twists {
+            for g in self.grips.iter().filter(|g| self.fixes(g, t)) {
+                out += &format!("{} | {}\n", t.family, g.name);
+            }
+        }
+        out += "\n";
+
+        // Grips around the same axis have no pieces in common, except for tips,
+        // which are inside their parent grips
+        let mut axes: Vec<(Vector, Vec<&str>)> = vec![];
+        for g in &self.grips {
+            if let Some(parent) = g.parent {
+                out += &format!("{} < {parent}\n", g.name);
+                continue;
+            }
+            match axes.iter_mut().find(|(dir, _)| parallel(*dir, g.dir)) {
+                Some((_, names)) => names.push(g.name),
+                None => axes.push((g.dir, vec![g.name])),
+            }
+        }
+        for (_, names) in &axes {
+            if names.len() > 1 {
+                out += &format!("{}\n", names.join(" # "));
+            }
+        }
+        let grips = self.grips.iter().filter(|g| g.parent.is_none());
+        for (a, b) in grips.tuple_combinations() {
+            if self.is_disjoint(a.dir, b.dir) {
+                out += &format!("{} # {}\n", a.name, b.name);
+            }
+        }
+        // Only the parent of a tip moves its pieces
+        for (i, tip) in self.grips.iter().enumerate() {
+            if tip.parent.is_none() {
+                continue;
+            }
+            for (j, g) in self.grips.iter().enumerate() {
+                if !parallel(tip.dir, g.dir) && (g.parent.is_none() || i < j) {
+                    out += &format!("{} # {}\n", tip.name, g.name);
+                }
+            }
+        }
+        out += "\n";
+
+        out += &twist_relations(
+            &self.twists,
+            |t| (t.family, t.order),
+            |t, amount| {
+                let angle = -TAU * amount as f64 / t.order as f64;
+                self.grips
+                    .iter()
+                    // Handled by commutation
+                    .filter(|g| !parallel(g.dir, t.dir) && !self.misses(g, t) && !self.fixes(g, t))
+                    .filter_map(|g| {
+                        let new = self.find_grip(rotate(g.dir, t.dir, angle), g)?;
+                        Some((g.name, new.name))
+                    })
+                    .collect()
+            },
+        );
+
+        out
+    }
+}
+
+/// Returns relations `NEW = OLD * FAMILY AMOUNT` for every twist by up to half
+/// of its order.
+///
+/// `family_and_order` returns the family and order of a twist, and
+/// `rewrites(twist, amount)` returns each grip `OLD` that the twist affects
+/// along with the grip `NEW` that it becomes. Each relation is also parsed in
+/// reverse, so half-turn relations are only listed in one direction.
+pub(crate) fn twist_relations<'a, T>(
+    twists: &'a [T],
+    family_and_order: impl Fn(&'a T) -> (&'a str, i32),
+    mut rewrites: impl FnMut(&'a T, i32) -> Vec<(&'a str, &'a str)>,
+) -> String {
+    let mut out = String::new();
+    let mut seen = BTreeSet::new();
+    for twist in twists {
+        let (family, order) = family_and_order(twist);
+        for amount in 1..=order / 2 {
+            for (old, new) in rewrites(twist, amount) {
+                let inverse_amount = (order - amount) % order;
+                if seen.contains(&(old, new, family, inverse_amount)) {
+                    continue;
+                }
+                seen.insert((new, old, family, amount));
+                let amount_str = if amount == 1 {
+                    String::new()
+                } else {
+                    amount.to_string()
+                };
+                out += &format!("{new} = {old} * {family}{amount_str}\n");
+            }
+        }
+    }
+    out
+}
+
+fn neg(v: Vector) -> Vector {
+    v.map(|x| -x)
+}
+fn dot(a: Vector, b: Vector) -> f64 {
+    std::iter::zip(a, b).map(|(x, y)| x * y).sum()
+}
+fn normalize(v: Vector) -> Vector {
+    let len = dot(v, v).sqrt();
+    v.map(|x| x / len)
+}
+fn approx_eq(a: Vector, b: Vector) -> bool {
+    std::iter::zip(a, b).all(|(x, y)| (x - y).abs() < 1e-6)
+}
+fn parallel(a: Vector, b: Vector) -> bool {
+    approx_eq(a, b) || approx_eq(a, neg(b))
+}
+/// Rotates `v` counterclockwise by `angle` around the 3D unit vector `axis`,
+/// leaving the fourth coordinate unchanged.
+fn rotate(v: Vector, axis: Vector, angle: f64) -> Vector {
+    let [x, y, z, w] = v;
+    let [kx, ky, kz, _] = axis;
+    let (sin, cos) = angle.sin_cos();
+    let k_dot_v = kx * x + ky * y + kz * z;
+    let cross = [ky * z - kz * y, kz * x - kx * z, kx * y - ky * x];
+    let [x, y, z] = [(x, kx, cross[0]), (y, ky, cross[1]), (z, kz, cross[2])]
+        .map(|(v, k, c)| v * cos + c * sin + k * k_dot_v * (1.0 - cos));
+    [x, y, z, w]
+}
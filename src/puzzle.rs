@@ -0,0 +1,497 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    ops::Range,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use cubing::alg::Move;
+use itertools::Itertools;
+
+use crate::{
+    Constraint, Grip, MoveSeq, ParseError, Region, Relation, Symmetry, constraint::is_constraint,
+    grip_axis, parse_alg, symmetry::symmetry_group, validate_grip_name,
+};
+
+/// Puzzle description parsed from a list of relations.
+///
+/// Each line is one of:
+///
+/// - a relation `NEW = OLD * moves`, which may have a transformed grip on
+///   both sides, such as `U × (R) = F × (U')` or `U × (D) = U`
+/// - a grip order `GRIP: order`, such as `U: 4`
+/// - a commutation `GRIP | GRIP | ...`, such as `R | L`, declaring that the
+///   moves of all listed grips commute with each other
+/// - a [`Constraint`], such as `U # D`, `at most 3 of U D F B R L`, or `u < U`
+/// - a rotation `rotation FAMILY ...`, such as `rotation x y z`, declaring
+///   move families that rotate the whole puzzle
+/// - a [`Symmetry`] `symmetry (GRIP GRIP ...)...`, such as `symmetry (U F
+///   R)(D B L)`, which adds the image of every relation, grip order,
+///   commutation, and constraint under every combination of symmetries
+/// - a section header `[name]`, which only organizes the lines
+/// - an include `include "file"`, which reads the lines of another file
+///
+/// `//` starts a comment anywhere in a line, and `#` starts a comment at the
+/// start of a line (elsewhere it is part of a constraint).
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Puzzle {
+    pub relations: Vec<Relation>,
+    /// Number of turns of each grip that return it to the identity. Amounts of
+    /// moves of grips with no declared order are never reduced.
+    pub orders: BTreeMap<String, i32>,
+    /// Pairs of distinct grips whose moves commute, stored in both orders.
+    pub commuting: BTreeSet<(String, String)>,
+    /// Constraints used to remove regions that cannot contain any pieces.
+    pub constraints: Vec<Constraint>,
+    /// Move families that rotate the whole puzzle, such as `x`, which
+    /// relabel grips instead of splitting regions.
+    pub rotations: BTreeSet<String>,
+    /// Generators of the symmetries of the puzzle, which are applied to every
+    /// line other than rotations.
+    pub symmetries: Vec<Symmetry>,
+    /// Relations generated from the relation lines by the symmetries, which
+    /// are also in [`Puzzle::relations`] along with their inverses.
+    pub generated: Vec<Relation>,
+}
+impl FromStr for Puzzle {
+    type Err = String;
+
+    /// Parses relations, reading included files relative to the current
+    /// directory.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::from_str_in_dir(s, Path::new(".")).map_err(|errors| errors.iter().join("\n"))
+    }
+}
+impl Puzzle {
+    /// Parses relations, reading included files relative to `dir`.
+    ///
+    /// Returns every error in the relations if any line is invalid.
+    pub fn from_str_in_dir(s: &str, dir: &Path) -> Result<Self, Vec<ParseError>> {
+        Self::parse(s, dir, vec![])
+    }
+
+    /// Parses relations read from the file at `path`, reading included files
+    /// relative to it. Including `path` itself is an include cycle.
+    ///
+    /// Returns every error in the relations if any line is invalid.
+    pub fn from_str_in_file(s: &str, path: &Path) -> Result<Self, Vec<ParseError>> {
+        let dir = path.parent().unwrap_or(Path::new("."));
+        Self::parse(s, dir, path.canonicalize().into_iter().collect())
+    }
+
+    /// Parses relations, reading included files relative to `dir`. `stack` is
+    /// the list of files that `s` is from, to detect include cycles.
+    fn parse(s: &str, dir: &Path, mut stack: Vec<PathBuf>) -> Result<Self, Vec<ParseError>> {
+        let mut ret = Self::default();
+        let mut errors = vec![];
+
+        let mut lines = vec![];
+        read_lines(s, dir, None, &mut stack, &mut lines, &mut errors);
+
+        // Relations are parsed after all orders are known so that their
+        // transforms can be reduced.
+        let mut relation_lines = vec![];
+        let mut symmetry_lines = vec![];
+        for line in &lines {
+            if let Some(symmetry) = line.text.strip_prefix("symmetry ") {
+                match symmetry.parse() {
+                    Ok(symmetry) => ret.symmetries.push(symmetry),
+                    Err(e) => errors.push(line.error(symmetry.trim(), e)),
+                }
+                symmetry_lines.push(line);
+            } else if let Some(i) = line.text.find('#')
+                && line.text.contains('=')
+            {
+                let message = "`#` starts a comment only at the start of a line; \
+                               use `//` for a comment after a relation";
+                errors.push(line.error(&line.text[i..], message));
+            } else if !is_constraint(&line.text) && line.text.contains('=') {
+                relation_lines.push(line);
+            } else if let Err(e) = ret.parse_line(line) {
+                errors.push(e);
+            }
+        }
+        let group = match symmetry_group(&ret.symmetries) {
+            Ok(group) => group,
+            Err(e) => {
+                let line = symmetry_lines.last().expect("group has symmetries");
+                errors.push(line.error(&line.text, e));
+                vec![Symmetry::default()]
+            }
+        };
+        for symmetry in &group[1..] {
+            ret.add_image(symmetry);
+        }
+
+        let mut seeds = vec![];
+        for line in relation_lines {
+            match ret.parse_relation(line) {
+                Ok(seed) => seeds.push((line.number, seed)),
+                Err(e) => errors.push(e),
+            }
+        }
+
+        // Add the relations in the order they were written and then their
+        // images under each symmetry. Images that are already relations are
+        // skipped, but lines that duplicate each other are kept so that they
+        // are reported as redundant.
+        for (i, symmetry) in group.iter().enumerate() {
+            for (line_number, (new_grip, grip_to_replace)) in &seeds {
+                let relation = Relation {
+                    new_grip: symmetry.grip(new_grip, &ret),
+                    grip_to_replace: symmetry.grip(grip_to_replace, &ret),
+                    line: Some(*line_number),
+                };
+                if i == 0 {
+                    ret.add_relation(relation);
+                    continue;
+                }
+                let is_new = ret.relations.iter().all(|r| {
+                    (&r.new_grip, &r.grip_to_replace)
+                        != (&relation.new_grip, &relation.grip_to_replace)
+                });
+                if is_new {
+                    ret.add_relation(relation.clone());
+                    ret.generated.push(relation);
+                }
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(ret)
+        } else {
+            errors.sort_by_key(|e| (e.line, e.columns.start));
+            Err(errors)
+        }
+    }
+
+    /// Adds the image of every grip order, commutation, and constraint under a
+    /// symmetry.
+    fn add_image(&mut self, symmetry: &Symmetry) {
+        for (grip_name, &order) in &self.orders.clone() {
+            self.orders
+                .entry(symmetry.grip_name(grip_name))
+                .or_insert(order);
+        }
+        for (a, b) in self.commuting.clone() {
+            self.commuting
+                .insert((symmetry.grip_name(&a), symmetry.grip_name(&b)));
+        }
+        for constraint in self.constraints.clone() {
+            let image = symmetry.constraint(&constraint);
+            if !self.constraints.contains(&image) {
+                self.constraints.push(image);
+            }
+        }
+    }
+
+    /// Parses a line that is not a relation.
+    fn parse_line(&mut self, line: &Line) -> Result<(), ParseError> {
+        let text = line.text.as_str();
+        if let Some(families) = text.strip_prefix("rotation ") {
+            for family in families.split_whitespace() {
+                validate_grip_name(family).map_err(|e| line.error(family, e))?;
+                self.rotations.insert(family.to_owned());
+            }
+        } else if is_constraint(text) {
+            let constraint = text.parse().map_err(|e| line.error(text, e))?;
+            self.constraints.push(constraint);
+        } else if text.contains('|') {
+            let grip_names: Vec<&str> = text.split('|').map(|s| s.trim()).collect();
+            for grip_name in &grip_names {
+                validate_grip_name(grip_name).map_err(|e| line.error(grip_name, e))?;
+            }
+            for a in &grip_names {
+                for b in &grip_names {
+                    if a != b {
+                        self.commuting.insert((a.to_string(), b.to_string()));
+                    }
+                }
+            }
+        } else if let Some((lhs, rhs)) = text.split_once(':') {
+            let (grip_name, order) = (lhs.trim(), rhs.trim());
+            validate_grip_name(grip_name).map_err(|e| line.error(grip_name, e))?;
+            let order = order
+                .parse()
+                .ok()
+                .filter(|&order| order > 0)
+                .ok_or_else(|| {
+                    line.error(
+                        order,
+                        format!("invalid order {order:?} for grip {grip_name}"),
+                    )
+                })?;
+            self.orders.insert(grip_name.to_owned(), order);
+        } else {
+            return Err(line.error(text, "relation line is missing '='"));
+        }
+        Ok(())
+    }
+
+    /// Parses a relation line, such as `U = F * R`, and returns the new grip
+    /// and the grip to replace.
+    fn parse_relation(&self, line: &Line) -> Result<(Grip, Grip), ParseError> {
+        let text = line.text.as_str();
+        if !text.contains(['*', '×']) {
+            return Err(line.error(text, "relation line is missing '*' or '×'"));
+        }
+        let (lhs, rhs) = text.split_once('=').expect("line contains '='");
+        let (lhs, rhs) = (self.parse_grip(line, lhs)?, self.parse_grip(line, rhs)?);
+        // Replace the side with the longer transform, or the right side if
+        // they are the same length
+        let (new_grip, grip_to_replace) = if lhs.transform.len() > rhs.transform.len() {
+            (rhs, lhs)
+        } else {
+            (lhs, rhs)
+        };
+
+        Ok((new_grip, grip_to_replace))
+    }
+
+    /// Adds a relation and its inverse.
+    fn add_relation(&mut self, relation: Relation) {
+        let Relation {
+            new_grip,
+            grip_to_replace,
+            line,
+        } = &relation;
+        // `OLD × (T1) = NEW × (T2)` implies `NEW × (T2 T1') = OLD`
+        let mut inverse_transform = new_grip.transform.clone();
+        for m in grip_to_replace.transform.inverse(self).iter() {
+            inverse_transform.push_back(m.clone(), self);
+        }
+        // Add inverse relation
+        self.relations.push(Relation {
+            new_grip: Grip::new(grip_to_replace.grip_name.clone()),
+            grip_to_replace: Grip {
+                grip_name: new_grip.grip_name.clone(),
+                transform: inverse_transform,
+            },
+            line: *line,
+        });
+        // Add original relation
+        self.relations.push(relation);
+    }
+
+    /// Parses a grip followed by any number of transforms, such as `U`,
+    /// `F * R U'`, or `U × (R) × (U')`, which is part of `line`.
+    fn parse_grip(&self, line: &Line, s: &str) -> Result<Grip, ParseError> {
+        let mut parts = s.split(['*', '×']);
+        let grip_name = parts.next().unwrap_or_default().trim();
+        validate_grip_name(grip_name).map_err(|e| line.error(grip_name, e))?;
+        let mut ret = Grip::new(grip_name.to_owned());
+        for part in parts {
+            let alg = parse_alg(part).map_err(|e| line.error_in(part, e.columns, e.message))?;
+            for m in MoveSeq::from_alg(&alg, self).iter() {
+                ret.transform.push_back(m.clone(), self);
+            }
+        }
+        Ok(ret)
+    }
+
+    /// Returns whether moves of two grips or move families commute. Layers on
+    /// the same axis always commute, and commutations declared for a face
+    /// apply to all of its layers.
+    pub fn commute(&self, a: &str, b: &str) -> bool {
+        let (a, b) = (grip_axis(a), grip_axis(b));
+        a == b || self.commuting.contains(&(a.to_owned(), b.to_owned()))
+    }
+
+    /// Returns whether a move rotates the whole puzzle.
+    pub fn is_rotation(&self, m: &Move) -> bool {
+        self.rotations.contains(&m.quantum.family)
+    }
+
+    /// Returns whether a region can contain any pieces according to the
+    /// puzzle's constraints.
+    pub fn is_possible(&self, region: &Region) -> bool {
+        self.constraints.iter().all(|c| c.allows(region))
+    }
+
+    /// Reduces a move amount into the range `(-order/2, order/2]` if the grip's
+    /// order is known, so that `U3` becomes `U'` and `U2'` becomes `U2` on a
+    /// grip of order 4.
+    pub fn reduce_amount(&self, family: &str, amount: i32) -> i32 {
+        match self.orders.get(grip_axis(family)) {
+            Some(&order) => {
+                let amount = amount.rem_euclid(order);
+                if amount > order / 2 {
+                    amount - order
+                } else {
+                    amount
+                }
+            }
+            None => amount,
+        }
+    }
+}
+
+/// Line of relations.
+struct Line {
+    /// Line number in the relations, starting at 1.
+    number: usize,
+    /// Text of the line without comments or surrounding whitespace.
+    text: String,
+    /// Column of `text` in characters, starting at 1.
+    column: usize,
+    /// Columns of the outermost include, and the file and line number that
+    /// `text` is from, if it is from an included file.
+    include: Option<(Range<usize>, PathBuf, usize)>,
+}
+impl Line {
+    /// Returns an error at `part`, which is a substring of the text.
+    fn error(&self, part: &str, message: impl Into<String>) -> ParseError {
+        let len = part.chars().count().max(1);
+        self.error_in(part, 1..len + 1, message.into())
+    }
+    /// Returns an error at `columns` of `part`, which is a substring of the
+    /// text. Errors in included files are at the outermost include.
+    fn error_in(&self, part: &str, columns: Range<usize>, message: String) -> ParseError {
+        if let Some((include_columns, path, line)) = &self.include {
+            return ParseError {
+                line: self.number,
+                columns: include_columns.clone(),
+                message: format!("{}:{line}: {message}", path.display()),
+            };
+        }
+        let offset = part.as_ptr() as usize - self.text.as_ptr() as usize;
+        let start = self.column + self.text[..offset].chars().count() - 1;
+        ParseError {
+            line: self.number,
+            columns: start + columns.start..start + columns.end,
+            message,
+        }
+    }
+}
+
+/// Appends the non-empty lines of `s` without comments or section headers to
+/// `lines`, and reads included files relative to `dir`.
+///
+/// `include` is the file that `s` is from and the line number and columns of
+/// the outermost include, if `s` is included. `stack` is the list of files
+/// being included, to detect include cycles.
+fn read_lines(
+    s: &str,
+    dir: &Path,
+    include: Option<(&Path, usize, &Range<usize>)>,
+    stack: &mut Vec<PathBuf>,
+    lines: &mut Vec<Line>,
+    errors: &mut Vec<ParseError>,
+) {
+    for (i, raw_line) in s.lines().enumerate() {
+        let text = raw_line.split("//").next().unwrap_or_default().trim();
+        if text.is_empty()
+            || text.starts_with('#')
+            || text.starts_with('[') && text.ends_with(']') && !text.contains('=')
+        {
+            continue;
+        }
+        let offset = text.as_ptr() as usize - raw_line.as_ptr() as usize;
+        let column = raw_line[..offset].chars().count() + 1;
+        let line = match include {
+            Some((path, number, columns)) => Line {
+                number,
+                text: text.to_owned(),
+                column,
+                include: Some((columns.clone(), path.to_owned(), i + 1)),
+            },
+            None => Line {
+                number: i + 1,
+                text: text.to_owned(),
+                column,
+                include: None,
+            },
+        };
+
+        let Some(file) = text.strip_prefix("include ") else {
+            lines.push(line);
+            continue;
+        };
+        let Some(file) = file
+            .trim()
+            .strip_prefix('"')
+            .and_then(|f| f.strip_suffix('"'))
+        else {
+            errors.push(line.error(&line.text, "include is missing quotes"));
+            continue;
+        };
+        let path = dir.join(file);
+        let contents = match path
+            .canonicalize()
+            .and_then(|canonical_path| Ok((canonical_path, std::fs::read_to_string(&path)?)))
+        {
+            Ok((canonical_path, contents)) => {
+                if let Some(i) = stack.iter().position(|p| *p == canonical_path) {
+                    let cycle = stack[i..]
+                        .iter()
+                        .chain([&canonical_path])
+                        .map(|p| p.display())
+                        .join(" → ");
+                    let message = format!("files include each other forever: {cycle}");
+                    errors.push(line.error(&line.text, message));
+                    continue;
+                }
+                stack.push(canonical_path);
+                contents
+            }
+            Err(e) => {
+                errors.push(line.error(&line.text, format!("error reading {path:?}: {e}")));
+                continue;
+            }
+        };
+        let columns = match include {
+            Some((_, _, columns)) => columns.clone(),
+            None => column..column + text.chars().count(),
+        };
+        let dir = path.parent().unwrap_or(Path::new("."));
+        read_lines(
+            &contents,
+            dir,
+            Some((&path, line.number, &columns)),
+            stack,
+            lines,
+            errors,
+        );
+        stack.pop();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn errors(s: &str, dir: &Path) -> Vec<String> {
+        match Puzzle::from_str_in_dir(s, dir) {
+            Ok(_) => vec![],
+            Err(errors) => errors.into_iter().map(|e| e.message).collect(),
+        }
+    }
+
+    #[test]
+    fn include_cycle() {
+        let dir = std::env::temp_dir().join(format!("grippy-include-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("a.txt"), "R: 4\ninclude \"b.txt\"").unwrap();
+        std::fs::write(dir.join("b.txt"), "include \"a.txt\"").unwrap();
+        let errors = errors("include \"a.txt\"", &dir);
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].contains("files include each other forever"));
+    }
+
+    #[test]
+    fn sections_and_comments() {
+        let puzzle: Puzzle = "[faces]\n# orders\nR: 4 // quarter turns\n\n[commuting]\nR | L"
+            .parse()
+            .unwrap();
+        assert_eq!(puzzle.orders["R"], 4);
+        assert!(puzzle.commute("R", "L"));
+    }
+
+    #[test]
+    fn hash_after_relation() {
+        let errors = errors("U = F * R # comment", Path::new("."));
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].contains("use `//` for a comment after a relation"));
+    }
+}
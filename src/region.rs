@@ -0,0 +1,82 @@
+use std::{collections::BTreeSet, fmt};
+
+use cubing::alg::Move;
+use itertools::Itertools;
+use serde::Serialize;
+
+use crate::{Grip, Puzzle, move_grip_names};
+
+#[derive(Debug, Default, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+pub struct Region {
+    pub include: BTreeSet<Grip>,
+    pub exclude: BTreeSet<Grip>,
+}
+impl fmt::Display for Region {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let s = itertools::chain(
+            self.include.iter().map(|s| format!("{s}")),
+            self.exclude.iter().map(|s| format!("!{s}")),
+        )
+        .join(", ");
+
+        write!(f, "{{{s}}}")
+    }
+}
+impl Region {
+    pub fn has_grip(&self, grip: Grip) -> Option<bool> {
+        if self.include.contains(&grip) {
+            Some(true)
+        } else if self.exclude.contains(&grip) {
+            Some(false)
+        } else {
+            None
+        }
+    }
+    /// returns `(not_affected, affected)`
+    ///
+    /// A move that spans several layer grips affects a region if the region
+    /// is in any of them, so the region may be split into several affected
+    /// regions.
+    ///
+    /// A rotation of the whole puzzle affects every region without splitting
+    /// it, and only relabels its grips.
+    ///
+    /// Returns an error if the relations rewrite a grip forever.
+    pub fn do_move(
+        self,
+        m: Move,
+        puzzle: &Puzzle,
+    ) -> Result<(Option<Region>, Vec<Region>), String> {
+        if puzzle.is_rotation(&m) {
+            return Ok((None, vec![self.do_move_unchecked(m, puzzle)?]));
+        }
+        let mut not_affected = self;
+        let mut affected = vec![];
+        for grip in move_grip_names(&m).into_iter().map(Grip::new) {
+            match not_affected.has_grip(grip.clone()) {
+                Some(false) => (),
+                Some(true) => {
+                    affected.push(not_affected.do_move_unchecked(m, puzzle)?);
+                    return Ok((None, affected));
+                }
+                None => {
+                    let mut included = not_affected.clone().do_move_unchecked(m.clone(), puzzle)?;
+                    included.include.insert(grip.clone());
+                    affected.push(included);
+                    not_affected.exclude.insert(grip);
+                }
+            }
+        }
+        Ok((Some(not_affected), affected))
+    }
+    fn do_move_unchecked(mut self, m: Move, puzzle: &Puzzle) -> Result<Self, String> {
+        for set in [&mut self.include, &mut self.exclude] {
+            *set = std::mem::take(set)
+                .into_iter()
+                .map(|g| g.do_move(m.clone(), puzzle))
+                .collect::<Result<_, _>>()?;
+        }
+
+        Ok(self)
+    }
+}
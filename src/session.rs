@@ -0,0 +1,230 @@
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{DEFAULT_RELATIONS, Preset};
+
+/// Algorithm, relations, and puzzle preset being analyzed, which can be saved
+/// to a JSON file and shared.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Session {
+    /// Moves applied before the algorithm.
+    pub setup: String,
+    pub alg: String,
+    pub relations: String,
+    /// Preset that the relations were taken from, if any.
+    pub preset: Option<Preset>,
+}
+impl Session {
+    /// Reads a session from a JSON file.
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let contents = std::fs::read_to_string(path)
+            .map_err(|e| format!("error reading session from {path:?}: {e}"))?;
+        serde_json::from_str(&contents).map_err(|e| format!("invalid session {path:?}: {e}"))
+    }
+
+    /// Returns the relations that a permalink without relations has.
+    fn default_relations(preset: Option<Preset>) -> String {
+        preset.map_or(DEFAULT_RELATIONS.to_string(), |p| p.relations())
+    }
+
+    /// Returns whether the relations differ from those of the preset, or the
+    /// default relations if there is no preset.
+    pub fn has_edited_relations(&self) -> bool {
+        self.relations != Self::default_relations(self.preset)
+    }
+
+    /// Returns a URL query string that encodes the session, such as
+    /// `alg=R+U+R%27+U%27&preset=3x3x3`. The setup is omitted if it is empty,
+    /// and the relations are omitted if they are the relations of the preset,
+    /// or the default relations if there is no preset.
+    pub fn to_permalink(&self) -> String {
+        let mut params = vec![];
+        if !self.setup.is_empty() {
+            params.push(format!("setup={}", encode(&self.setup)));
+        }
+        params.push(format!("alg={}", encode(&self.alg)));
+        if let Some(preset) = self.preset {
+            params.push(format!("preset={}", encode(preset.name())));
+        }
+        if self.has_edited_relations() {
+            params.push(format!("relations={}", encode(&self.relations)));
+        }
+        params.join("&")
+    }
+
+    /// Parses a permalink returned by [`Session::to_permalink`], which may be
+    /// part of a URL, or an alg.cubing.net or twizzle URL. Unknown parameters
+    /// are ignored.
+    pub fn from_permalink(s: &str) -> Result<Self, String> {
+        if let Some(ret) = Self::from_alg_url(s) {
+            return ret;
+        }
+        let mut ret = Self::default();
+        let mut relations = None;
+        for (key, value) in query_params(s) {
+            let value = decode(value)?;
+            match key {
+                "setup" => ret.setup = value,
+                "alg" => ret.alg = value,
+                "preset" => ret.preset = Some(value.parse()?),
+                "relations" => relations = Some(value),
+                _ => (),
+            }
+        }
+        ret.relations = relations.unwrap_or_else(|| Self::default_relations(ret.preset));
+        Ok(ret)
+    }
+
+    /// Parses an alg.cubing.net or twizzle URL, using the relations of the
+    /// preset for its puzzle.
+    ///
+    /// Returns `None` if `s` is not such a URL.
+    pub fn from_alg_url(s: &str) -> Option<Result<Self, String>> {
+        let s = s.trim();
+        let host = s
+            .strip_prefix("https://")
+            .or_else(|| s.strip_prefix("http://"))
+            .unwrap_or(s)
+            .split(['/', '?'])
+            .next()?;
+        if !host.ends_with("cubing.net") && !host.ends_with("twizzle.net") {
+            return None;
+        }
+        // Wait for the URL to be complete while it is being typed
+        let keys = [
+            "alg",
+            "setup",
+            "setup-alg",
+            "experimental-setup-alg",
+            "puzzle",
+        ];
+        if !query_params(s).any(|(key, _)| keys.contains(&key)) {
+            return None;
+        }
+        // alg.cubing.net writes spaces as `_` and primes as `-`
+        let decode = |value: &str| {
+            if host == "alg.cubing.net" {
+                decode(&value.replace('_', " ").replace('-', "'"))
+            } else {
+                decode(value)
+            }
+        };
+
+        let parse = || {
+            let mut alg = String::new();
+            let mut setup = String::new();
+            let mut preset = Preset::Cube3x3x3;
+            for (key, value) in query_params(s) {
+                match key {
+                    "alg" => alg = decode(value)?,
+                    "setup" | "setup-alg" | "experimental-setup-alg" => setup = decode(value)?,
+                    "puzzle" => {
+                        let puzzle = decode(value)?;
+                        preset = puzzle
+                            .parse()
+                            .map_err(|_| format!("no preset for puzzle {puzzle:?}"))?;
+                    }
+                    _ => (),
+                }
+            }
+            Ok(Self {
+                setup,
+                alg,
+                relations: preset.relations(),
+                preset: Some(preset),
+            })
+        };
+        Some(parse())
+    }
+
+    /// Writes the session to a JSON file.
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        let contents = serde_json::to_string_pretty(self).map_err(|e| e.to_string())?;
+        std::fs::write(path, contents + "\n")
+            .map_err(|e| format!("error writing session to {path:?}: {e}"))
+    }
+}
+
+/// Returns the parameters of the query string of a URL, or of `s` if it has
+/// no `?`.
+fn query_params(s: &str) -> impl Iterator<Item = (&str, &str)> {
+    let s = s.trim();
+    let query = s.split_once('?').map_or(s, |(_, query)| query);
+    let query = query.split('#').next().unwrap_or_default();
+    query
+        .split('&')
+        .filter(|param| !param.is_empty())
+        .map(|param| param.split_once('=').unwrap_or((param, "")))
+}
+
+/// Percent-encodes a string for a URL query, with `+` for spaces.
+fn encode(s: &str) -> String {
+    let mut ret = String::new();
+    for b in s.bytes() {
+        match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                ret.push(b as char);
+            }
+            b' ' => ret.push('+'),
+            _ => ret += &format!("%{b:02X}"),
+        }
+    }
+    ret
+}
+
+/// Decodes a string encoded by [`encode`].
+fn decode(s: &str) -> Result<String, String> {
+    let mut bytes = vec![];
+    let mut rest = s.as_bytes();
+    while let Some((&b, after)) = rest.split_first() {
+        rest = after;
+        match b {
+            b'+' => bytes.push(b' '),
+            b'%' => {
+                let byte = rest
+                    .get(..2)
+                    .and_then(|hex| std::str::from_utf8(hex).ok())
+                    .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+                    .ok_or_else(|| format!("invalid escape in {s:?}"))?;
+                bytes.push(byte);
+                rest = &rest[2..];
+            }
+            _ => bytes.push(b),
+        }
+    }
+    String::from_utf8(bytes).map_err(|_| format!("invalid UTF-8 in {s:?}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn permalink_round_trip() {
+        let session = Session {
+            setup: "R U".to_string(),
+            alg: "[R, U] // 100% commutator".to_string(),
+            relations: "U × (R) = F\nR | L\n".to_string(),
+            preset: Some(Preset::Cube3x3x3),
+        };
+        let permalink = session.to_permalink();
+        assert!(!permalink.contains(['×', '\n', ' ']));
+        assert_eq!(Session::from_permalink(&permalink), Ok(session.clone()));
+        let url = format!("https://example.com/?{permalink}");
+        assert_eq!(Session::from_permalink(&url), Ok(session));
+    }
+
+    #[test]
+    fn preset_relations_are_omitted() {
+        let session = Session {
+            alg: "R".to_string(),
+            relations: Preset::Cube3x3x3.relations(),
+            preset: Some(Preset::Cube3x3x3),
+            ..Default::default()
+        };
+        assert_eq!(session.to_permalink(), "alg=R&preset=3x3x3");
+        assert_eq!(Session::from_permalink("alg=R&preset=3x3x3"), Ok(session));
+    }
+}
@@ -0,0 +1,177 @@
+use std::{collections::BTreeMap, fmt, str::FromStr, sync::Arc};
+
+use cubing::alg::{Move, QuantumMove};
+use itertools::Itertools;
+
+use crate::{Constraint, Grip, Puzzle, validate_grip_name};
+
+/// Maximum number of elements of the group generated by the symmetries of a
+/// puzzle.
+const MAX_SYMMETRY_GROUP_SIZE: usize = 1000;
+
+/// Symmetry of a puzzle, which permutes its grips, such as `(U F R)(D B L)`.
+///
+/// Layers of a face are permuted with the face, so `(U F R)` also maps `2U` to
+/// `2F` and `Uw` to `Fw`.
+#[derive(Debug, Default, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Symmetry(BTreeMap<String, String>);
+impl fmt::Display for Symmetry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.0.is_empty() {
+            return write!(f, "()");
+        }
+        let mut done = vec![];
+        for start in self.0.keys() {
+            if done.contains(&start) {
+                continue;
+            }
+            let mut cycle = vec![start];
+            while let Some(next) = self.0.get(*cycle.last().expect("cycle is nonempty"))
+                && next != start
+            {
+                cycle.push(next);
+            }
+            write!(f, "({})", cycle.iter().join(" "))?;
+            done.extend(cycle);
+        }
+        Ok(())
+    }
+}
+impl FromStr for Symmetry {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut ret = Self::default();
+        let mut seen = vec![];
+        let mut rest = s.trim();
+        while !rest.is_empty() {
+            let Some((cycle, after)) = rest
+                .strip_prefix('(')
+                .and_then(|cycle| cycle.split_once(')'))
+            else {
+                return Err(format!("invalid cycle {rest:?} in symmetry"));
+            };
+            let grip_names = cycle
+                .split(|c: char| c == ',' || c.is_whitespace())
+                .filter(|name| !name.is_empty())
+                .collect::<Vec<_>>();
+            for &name in &grip_names {
+                validate_grip_name(name)?;
+                if seen.contains(&name) {
+                    return Err(format!("grip {name} appears more than once in symmetry"));
+                }
+                seen.push(name);
+            }
+            for (a, b) in grip_names.iter().zip(grip_names.iter().cycle().skip(1)) {
+                if a != b {
+                    ret.0.insert(a.to_string(), b.to_string());
+                }
+            }
+            rest = after.trim_start();
+        }
+        Ok(ret)
+    }
+}
+impl Symmetry {
+    /// Returns the symmetry that applies `self` and then `other`.
+    pub fn then(&self, other: &Self) -> Self {
+        let mut ret = Self::default();
+        for name in self.0.keys().chain(other.0.keys()) {
+            let image = other.axis(self.axis(name));
+            if image != name {
+                ret.0.insert(name.clone(), image.to_owned());
+            }
+        }
+        ret
+    }
+
+    fn axis<'a>(&'a self, axis: &'a str) -> &'a str {
+        self.0.get(axis).map_or(axis, |s| s.as_str())
+    }
+
+    /// Returns the image of a grip name, keeping its layer prefix.
+    pub fn grip_name(&self, grip_name: &str) -> String {
+        let axis_start = grip_name
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(grip_name.len());
+        let (layer, axis) = grip_name.split_at(axis_start);
+        format!("{layer}{}", self.axis(axis))
+    }
+
+    /// Returns the image of a move family, such as `R` or `Rw`.
+    pub fn family(&self, family: &str) -> String {
+        match family.strip_suffix('w') {
+            Some(axis) if !self.0.contains_key(family) && self.0.contains_key(axis) => {
+                format!("{}w", self.axis(axis))
+            }
+            _ => self.axis(family).to_owned(),
+        }
+    }
+
+    /// Returns the image of a grip.
+    pub fn grip(&self, grip: &Grip, puzzle: &Puzzle) -> Grip {
+        let mut ret = Grip::new(self.grip_name(&grip.grip_name));
+        for m in grip.transform.iter() {
+            ret.transform.push_back(self.move_(m), puzzle);
+        }
+        ret
+    }
+
+    /// Returns the image of a constraint.
+    pub fn constraint(&self, constraint: &Constraint) -> Constraint {
+        let names =
+            |grip_names: &[String]| grip_names.iter().map(|name| self.grip_name(name)).collect();
+        match constraint {
+            Constraint::AtMost { max, grip_names } => Constraint::AtMost {
+                max: *max,
+                grip_names: names(grip_names),
+            },
+            Constraint::AtLeast { min, grip_names } => Constraint::AtLeast {
+                min: *min,
+                grip_names: names(grip_names),
+            },
+            Constraint::Subgrip {
+                grip_name,
+                supergrip_name,
+            } => Constraint::Subgrip {
+                grip_name: self.grip_name(grip_name),
+                supergrip_name: self.grip_name(supergrip_name),
+            },
+        }
+    }
+
+    fn move_(&self, m: &Move) -> Move {
+        Move {
+            quantum: Arc::new(QuantumMove {
+                family: self.family(&m.quantum.family),
+                prefix: m.quantum.prefix.clone(),
+            }),
+            amount: m.amount,
+        }
+    }
+}
+
+/// Returns every element of the group generated by `generators`, starting
+/// with the identity.
+///
+/// Returns an error if the group has more than [`MAX_SYMMETRY_GROUP_SIZE`]
+/// elements.
+pub(crate) fn symmetry_group(generators: &[Symmetry]) -> Result<Vec<Symmetry>, String> {
+    let mut ret = vec![Symmetry::default()];
+    let mut i = 0;
+    while let Some(s) = ret.get(i).cloned() {
+        for g in generators {
+            let new = s.then(g);
+            if !ret.contains(&new) {
+                if ret.len() >= MAX_SYMMETRY_GROUP_SIZE {
+                    return Err(format!(
+                        "symmetries generate more than {MAX_SYMMETRY_GROUP_SIZE} symmetries",
+                    ));
+                }
+                ret.push(new);
+            }
+        }
+        i += 1;
+    }
+    Ok(ret)
+}
@@ -0,0 +1,143 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt,
+};
+
+use cubing::kpuzzle::KPuzzle;
+use itertools::Itertools;
+
+use crate::{
+    Analysis, MoveSeq, Puzzle, Region,
+    kpuzzle::{GripPositions, image, piece_names, region_pieces},
+    move_grip_names,
+};
+
+/// Result of checking an [`Analysis`] against a simulation of its algorithm on
+/// every piece of a KPuzzle.
+#[derive(Debug, Default, Clone)]
+pub struct Verification {
+    /// Number of pieces checked.
+    pub pieces: usize,
+    /// Descriptions of every disagreement between the analysis and the
+    /// simulation.
+    pub mismatches: Vec<String>,
+}
+impl fmt::Display for Verification {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.mismatches.is_empty() {
+            writeln!(f, "Verification: all {} pieces match", self.pieces)
+        } else {
+            writeln!(
+                f,
+                "Verification: {} mismatches in {} pieces",
+                self.mismatches.len(),
+                self.pieces,
+            )?;
+            for m in &self.mismatches {
+                writeln!(f, "    {m}")?;
+            }
+            Ok(())
+        }
+    }
+}
+impl Verification {
+    pub fn is_ok(&self) -> bool {
+        self.mismatches.is_empty()
+    }
+}
+
+/// Verifies an analysis by simulating its setup and moves on every piece of `kpuzzle`.
+///
+/// Each grip corresponds to the pieces moved by the KPuzzle move of the same
+/// name, and `G × (T)` to where those pieces are after `T`. Every piece must be
+/// in exactly one region, and the moves that affect the piece in the
+/// simulation must match the move mask and net move sequence of that region.
+pub fn verify(
+    analysis: &Analysis,
+    puzzle: &Puzzle,
+    kpuzzle: &KPuzzle,
+) -> Result<Verification, String> {
+    let region_pieces = region_pieces(kpuzzle, &analysis.regions)?;
+    let piece_names = piece_names(kpuzzle);
+
+    let mut expected = BTreeMap::<&Region, (&MoveSeq, &[bool])>::new();
+    for (move_seq, regions_by_mask) in &analysis.results {
+        for (move_mask, regions) in regions_by_mask {
+            for r in regions {
+                expected.insert(r, (move_seq, move_mask));
+            }
+        }
+    }
+
+    let mut grips = GripPositions::new(kpuzzle);
+    let mut transformations = vec![];
+    for m in analysis.all_moves() {
+        let mut affected = BTreeSet::new();
+        for grip_name in move_grip_names(m) {
+            affected.extend(grips.support(&grip_name)?.iter().copied());
+        }
+        let t = kpuzzle
+            .transformation_from_move(m)
+            .map_err(|e| format!("move {m} is not a move of the KPuzzle: {e}"))?;
+        transformations.push((m, affected, t));
+    }
+
+    let mut ret = Verification::default();
+    for (&piece, name) in &piece_names {
+        ret.pieces += 1;
+
+        let regions = region_pieces
+            .iter()
+            .filter(|(_, pieces)| pieces.contains(&piece))
+            .map(|(r, _)| *r)
+            .collect_vec();
+        let region = match regions[..] {
+            [] => {
+                ret.mismatches.push(format!("{name} is in no region"));
+                continue;
+            }
+            [r] => r,
+            _ => {
+                let regions = regions.iter().join(", ");
+                ret.mismatches
+                    .push(format!("{name} is in multiple regions: {regions}"));
+                continue;
+            }
+        };
+        let mut move_seq = MoveSeq::new();
+        let mut move_mask = vec![];
+        let mut position = piece;
+        for (i, (m, affected, t)) in transformations.iter().enumerate() {
+            let is_affected = affected.contains(&position);
+            move_mask.push(is_affected);
+            if is_affected && i >= analysis.setup.len() {
+                move_seq.push_back((*m).clone(), puzzle);
+            }
+            position = image(kpuzzle, t, &BTreeSet::from([position]))
+                .pop_first()
+                .expect("transformation is a permutation");
+        }
+
+        let Some(&(expected_move_seq, expected_move_mask)) = expected.get(region) else {
+            ret.mismatches
+                .push(format!("{name} is in region {region} with no result"));
+            continue;
+        };
+        if expected_move_mask != move_mask {
+            let show_mask =
+                |mask: &[bool]| mask.iter().map(|&b| if b { '1' } else { '0' }).join("");
+            ret.mismatches.push(format!(
+                "{name} in region {region} has move mask {} but {} was predicted",
+                show_mask(&move_mask),
+                show_mask(expected_move_mask),
+            ));
+        } else if *expected_move_seq != move_seq {
+            ret.mismatches.push(format!(
+                "{name} in region {region} has net move sequence ({move_seq}) \
+                 but ({expected_move_seq}) was predicted",
+            ));
+        }
+    }
+
+    Ok(ret)
+}